@@ -0,0 +1,229 @@
+//! # Passerine Derive
+//! Derive macros for converting Rust types to and from Passerine `Data`.
+//!
+//! `#[derive(PasserineData)]` implements `IntoData` and `FromData`
+//! (from `passerine::common::convert`) for structs and enums:
+//!
+//! - A struct with named fields is converted to a record, i.e. `{ x: 1, y: 2 }`.
+//! - A tuple struct is converted to a tuple,
+//!   or to its only field if it has one.
+//! - A unit struct is converted to the Unit, `()`.
+//! - An enum variant is converted to a label qualified by the name of the enum,
+//!   i.e. `Shape.Circle 1.0`.
+//!   The data inside the label follows the same rules as structs.
+//!
+//! Every field must implement `IntoData` and `FromData` as well.
+//! Type parameters are required to implement them too.
+//!
+//! ```
+//! use passerine::common::{data::Data, convert::{IntoData, FromData}};
+//! use passerine_derive::PasserineData;
+//!
+//! #[derive(PasserineData, Debug, Clone, PartialEq)]
+//! enum Shape {
+//!     Circle(f64),
+//!     Rect { width: f64, height: f64 },
+//! }
+//!
+//! let shape = Shape::Rect { width: 1.0, height: 2.0 };
+//! let data = shape.clone().into_data();
+//! assert_eq!(format!("{}", data), "Shape.Rect { height: 2, width: 1 }");
+//! assert_eq!(Shape::from_data(data), Ok(shape));
+//! ```
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as Tokens;
+use quote::{quote, format_ident};
+use syn::{
+    parse_macro_input,
+    DeriveInput, Data, Fields, Generics, Ident,
+};
+
+/// Implements `IntoData` and `FromData` for a struct or enum.
+/// See the crate documentation for how types are mapped to `Data`.
+#[proc_macro_derive(PasserineData)]
+pub fn derive_passerine_data(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match derive(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn derive(input: &DeriveInput) -> Result<Tokens, syn::Error> {
+    let name = &input.ident;
+    let into_generics = bounded(&input.generics, quote! { ::passerine::common::convert::IntoData });
+    let from_generics = bounded(&input.generics, quote! { ::passerine::common::convert::FromData });
+    let (into_impl, type_generics, into_where) = into_generics.split_for_impl();
+    let (from_impl, _,             from_where) = from_generics.split_for_impl();
+
+    let (into, from) = match &input.data {
+        Data::Struct(s) => {
+            let bindings = bindings(&s.fields);
+            let pattern  = pattern(quote! { #name }, &s.fields, &bindings);
+            let into     = into_data(&s.fields, &bindings);
+            let from     = from_data(quote! { #name }, &s.fields, quote! { data });
+            (
+                quote! { let #pattern = self; #into },
+                from,
+            )
+        },
+        Data::Enum(e) => {
+            let mut into_arms = vec![];
+            let mut from_arms = vec![];
+
+            for variant in e.variants.iter() {
+                let label = format!("{}.{}", name, variant.ident);
+                let ident = &variant.ident;
+                let bindings = bindings(&variant.fields);
+                let pattern  = pattern(quote! { #name::#ident }, &variant.fields, &bindings);
+                let into     = into_data(&variant.fields, &bindings);
+                let from     = from_data(quote! { #name::#ident }, &variant.fields, quote! { *inner });
+
+                into_arms.push(quote! {
+                    #pattern => ::passerine::common::data::Data::Label(
+                        Box::new(#label.to_string()),
+                        Box::new(#into),
+                    ),
+                });
+                from_arms.push(quote! {
+                    ::passerine::common::data::Data::Label(label, inner) if *label == #label => {
+                        #from
+                    },
+                });
+            }
+
+            let expected = format!("a {} label", name);
+            (
+                quote! { match self { #(#into_arms)* } },
+                quote! {
+                    match data {
+                        #(#from_arms)*
+                        other => Err(::passerine::common::convert::ConversionError::new(#expected, &other)),
+                    }
+                },
+            )
+        },
+        Data::Union(u) => return Err(syn::Error::new(
+            u.union_token.span,
+            "PasserineData can not be derived for unions",
+        )),
+    };
+
+    Ok(quote! {
+        impl #into_impl ::passerine::common::convert::IntoData for #name #type_generics #into_where {
+            fn into_data(self) -> ::passerine::common::data::Data {
+                #into
+            }
+        }
+
+        impl #from_impl ::passerine::common::convert::FromData for #name #type_generics #from_where {
+            fn from_data(data: ::passerine::common::data::Data)
+            -> Result<Self, ::passerine::common::convert::ConversionError> {
+                #from
+            }
+        }
+    })
+}
+
+/// Requires every type parameter to implement a conversion trait,
+/// so the fields that use the parameter can be converted.
+fn bounded(generics: &Generics, bound: Tokens) -> Generics {
+    let mut generics = generics.clone();
+    let params = generics.type_params().map(|p| p.ident.clone()).collect::<Vec<_>>();
+    let where_clause = generics.make_where_clause();
+    for param in params {
+        where_clause.predicates.push(syn::parse_quote! { #param: #bound });
+    }
+    generics
+}
+
+/// Names the local variables each field is bound to when destructuring.
+fn bindings(fields: &Fields) -> Vec<Ident> {
+    fields.iter().enumerate().map(|(index, field)| match &field.ident {
+        Some(ident) => format_ident!("field_{}", ident),
+        None        => format_ident!("field_{}", index),
+    }).collect()
+}
+
+/// Builds a pattern that destructures a struct or variant into its bindings.
+fn pattern(path: Tokens, fields: &Fields, bindings: &[Ident]) -> Tokens {
+    match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|f| &f.ident);
+            quote! { #path { #(#names: #bindings),* } }
+        },
+        Fields::Unnamed(_) => quote! { #path ( #(#bindings),* ) },
+        Fields::Unit       => quote! { #path },
+    }
+}
+
+/// Converts the bindings of a destructured struct or variant into `Data`.
+fn into_data(fields: &Fields, bindings: &[Ident]) -> Tokens {
+    let convert = quote! { ::passerine::common::convert::IntoData::into_data };
+
+    match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|f| f.ident.as_ref().unwrap().to_string());
+            quote! {{
+                let mut fields = vec![#((#names.to_string(), #convert(#bindings))),*];
+                // records are kept sorted by field name
+                fields.sort_by(|a, b| a.0.cmp(&b.0));
+                ::passerine::common::data::Data::Record(fields)
+            }}
+        },
+        Fields::Unnamed(_) if bindings.len() == 1 => {
+            let binding = &bindings[0];
+            quote! { #convert(#binding) }
+        },
+        Fields::Unnamed(_) => quote! {
+            ::passerine::common::data::Data::Tuple(vec![#(#convert(#bindings)),*])
+        },
+        Fields::Unit => quote! { ::passerine::common::data::Data::Unit },
+    }
+}
+
+/// Converts some `Data` back into a struct or variant.
+fn from_data(path: Tokens, fields: &Fields, data: Tokens) -> Tokens {
+    let convert = quote! { ::passerine::common::convert::FromData::from_data };
+    let error   = quote! { ::passerine::common::convert::ConversionError };
+
+    match fields {
+        Fields::Named(named) => {
+            let idents = named.named.iter().map(|f| &f.ident).collect::<Vec<_>>();
+            let names  = idents.iter().map(|i| i.as_ref().unwrap().to_string());
+            quote! {
+                match #data {
+                    ::passerine::common::data::Data::Record(mut fields) => Ok(#path {
+                        #(#idents: ::passerine::common::convert::take_field(&mut fields, #names)?),*
+                    }),
+                    other => Err(#error::new("a record", &other)),
+                }
+            }
+        },
+        Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => quote! {
+            Ok(#path(#convert(#data)?))
+        },
+        Fields::Unnamed(unnamed) => {
+            let size     = unnamed.unnamed.len();
+            let expected = format!("a tuple of {} items", size);
+            let items    = (0..size).map(|_| quote! { #convert(items.next().unwrap())? });
+            quote! {
+                match #data {
+                    ::passerine::common::data::Data::Tuple(items) if items.len() == #size => {
+                        let mut items = items.into_iter();
+                        Ok(#path(#(#items),*))
+                    },
+                    other => Err(#error::new(#expected, &other)),
+                }
+            }
+        },
+        Fields::Unit => quote! {
+            match #data {
+                ::passerine::common::data::Data::Unit => Ok(#path),
+                other => Err(#error::new("the Unit, '()'", &other)),
+            }
+        },
+    }
+}
@@ -0,0 +1,113 @@
+use passerine::common::{
+    data::Data,
+    convert::{IntoData, FromData, ConversionError},
+};
+use passerine_derive::PasserineData;
+
+#[derive(PasserineData, Debug, Clone, PartialEq)]
+struct Point {
+    y: f64,
+    x: f64,
+}
+
+#[derive(PasserineData, Debug, Clone, PartialEq)]
+struct Pair(String, bool);
+
+#[derive(PasserineData, Debug, Clone, PartialEq)]
+struct Meters(f64);
+
+#[derive(PasserineData, Debug, Clone, PartialEq)]
+struct Marker;
+
+#[derive(PasserineData, Debug, Clone, PartialEq)]
+enum Shape {
+    Empty,
+    Circle(f64),
+    Line(Point, Point),
+    Polygon { points: Vec<Point>, closed: bool },
+}
+
+#[derive(PasserineData, Debug, Clone, PartialEq)]
+enum Tree<T> {
+    Leaf(T),
+    Node(Vec<Tree<T>>),
+}
+
+fn round_trip<T: IntoData + FromData + Clone + PartialEq + std::fmt::Debug>(value: T) {
+    assert_eq!(T::from_data(value.clone().into_data()), Ok(value));
+}
+
+#[test]
+fn structs() {
+    assert_eq!(
+        Point { y: 2.0, x: 1.0 }.into_data(),
+        Data::Record(vec![
+            ("x".to_string(), Data::Real(1.0)),
+            ("y".to_string(), Data::Real(2.0)),
+        ]),
+    );
+    assert_eq!(
+        Pair("a".to_string(), true).into_data(),
+        Data::Tuple(vec![Data::String("a".to_string()), Data::Boolean(true)]),
+    );
+    assert_eq!(Meters(3.0).into_data(), Data::Real(3.0));
+    assert_eq!(Marker.into_data(), Data::Unit);
+
+    round_trip(Point { y: 2.0, x: 1.0 });
+    round_trip(Pair("a".to_string(), true));
+    round_trip(Meters(3.0));
+    round_trip(Marker);
+}
+
+#[test]
+fn enums() {
+    assert_eq!(
+        Shape::Circle(1.0).into_data(),
+        Data::Label(Box::new("Shape.Circle".to_string()), Box::new(Data::Real(1.0))),
+    );
+    assert_eq!(
+        format!("{}", Shape::Empty.into_data()),
+        "Shape.Empty ()",
+    );
+
+    round_trip(Shape::Empty);
+    round_trip(Shape::Circle(1.0));
+    round_trip(Shape::Line(Point { x: 0.0, y: 0.0 }, Point { x: 1.0, y: 1.0 }));
+    round_trip(Shape::Polygon { points: vec![Point { x: 0.0, y: 1.0 }], closed: false });
+}
+
+#[test]
+fn generics() {
+    let tree = Tree::Node(vec![Tree::Leaf("a".to_string()), Tree::Leaf("b".to_string())]);
+    assert_eq!(
+        format!("{}", tree.clone().into_data()),
+        "Tree.Node [Tree.Leaf a, Tree.Leaf b]",
+    );
+
+    round_trip(tree);
+    round_trip(Tree::Leaf(1.0));
+}
+
+#[test]
+fn mismatch() {
+    let missing = Data::Record(vec![("x".to_string(), Data::Real(1.0))]);
+    assert_eq!(
+        Point::from_data(missing.clone()),
+        Err(ConversionError::new("a record with the field 'y'", &missing)),
+    );
+
+    let label = Data::Label(Box::new("Color.Red".to_string()), Box::new(Data::Unit));
+    assert_eq!(
+        Shape::from_data(label.clone()),
+        Err(ConversionError::new("a Shape label", &label)),
+    );
+
+    assert!(Shape::from_data(Shape::Circle(1.0).into_data()).is_ok());
+    assert!(Pair::from_data(Data::Tuple(vec![Data::Unit])).is_err());
+}
+
+#[test]
+fn from_passerine() {
+    let shape = passerine::eval("Shape.Circle 2.0").unwrap();
+    assert_eq!(Shape::from_data(shape), Ok(Shape::Circle(2.0)));
+}
@@ -0,0 +1,829 @@
+use std::{
+    fmt,
+    fmt::Write,
+    convert::TryFrom,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::common::{
+    data::Data,
+    lambda::{Captured, Lambda, JUMP_WIDTH},
+    opcode::Opcode,
+    number::{split_number, try_build_number},
+    ffi::FFI,
+    span::Span,
+    source::Source,
+};
+
+/// Raised when assembly can not be assembled into a `Lambda`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssemblyError {
+    /// The line the error occurred on, starting at 1.
+    pub line:    usize,
+    pub message: String,
+}
+
+impl AssemblyError {
+    fn new(line: usize, message: &str) -> AssemblyError {
+        AssemblyError { line, message: message.to_string() }
+    }
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Assembly Error on line {}: {}", self.line, self.message)
+    }
+}
+
+/// Writes a `Lambda` as assembly, which `assemble` turns back into the same bytecode.
+///
+/// A lambda is written as its constants, captures, and code:
+///
+/// ```plain
+/// lambda {
+///     constants {
+///         real 2.0 -- 0
+///         lambda { -- 1
+///             code {
+///                 Load 0
+///                 Con 0
+///                 Mul
+///                 Return 1
+///             }
+///         }
+///     }
+///     captures { }
+///     code {
+///         Con 0
+///         Closure 1
+///         Call
+///     }
+/// }
+/// ```
+///
+/// Each instruction is the name of an opcode, followed by its operand, if any.
+/// Jumps refer to labels, like `Jump @12`, defined before an instruction by `@12:`;
+/// the disassembler names labels after the index of the instruction they point to.
+/// Everything after `--` on a line is a comment.
+///
+/// Constants are written as `unit`, `real 1.5`, `boolean true`, `string "text"`,
+/// `kind "Name"`, `label "Name" <data>`, `tuple { <data>... }`, `list { <data>... }`,
+/// `record { "field" <data>... }`, `host "name"`, or a nested `lambda { ... }`.
+/// Bytes that aren't valid instructions are kept as `Bytes { 1 2 3 }`.
+/// Constants that can't be written, like host functions without a name,
+/// are written as `unknown`, which fails to assemble.
+/// Spans are not kept, see `disassemble_with_source`.
+pub fn disassemble(lambda: &Lambda) -> String {
+    let mut output = String::new();
+    Disassembler { output: &mut output, indent: 0, source: false, lines: None }.lambda(lambda, "");
+    output
+}
+
+/// Writes a `Lambda` as assembly, like `disassemble`,
+/// but annotates instructions with the lines of source they were compiled from.
+/// Whenever the source line changes, it's written as a comment
+/// before the instructions it produced:
+///
+/// ```plain
+/// code {
+///     -- 1 | x = 2.0
+///     Con 0
+///     Save 0
+///     -- 2 | print (x * x)
+///     Load 0
+///     ...
+/// ```
+///
+/// As the annotations are comments, the output can still be assembled.
+pub fn disassemble_with_source(lambda: &Lambda) -> String {
+    let mut output = String::new();
+    Disassembler { output: &mut output, indent: 0, source: true, lines: None }.lambda(lambda, "");
+    output
+}
+
+/// Assembles a `Lambda` from assembly, see `disassemble` for the format.
+/// Assembly that refers to host functions must be assembled with `assemble_with_ffi`.
+/// Note that the resulting `Lambda` is not checked, see `common::verify`.
+pub fn assemble(source: &str) -> Result<Lambda, AssemblyError> {
+    assemble_with_ffi(source, &FFI::new())
+}
+
+/// Assembles a `Lambda`, resolving host functions by name in an `FFI`.
+pub fn assemble_with_ffi(source: &str, ffi: &FFI) -> Result<Lambda, AssemblyError> {
+    let mut assembler = Assembler { tokens: Assembler::lex(source)?, index: 0, ffi };
+    assembler.keyword("lambda")?;
+    let lambda = assembler.lambda()?;
+
+    if let Some((_, line)) = assembler.tokens.get(assembler.index) {
+        return Err(AssemblyError::new(*line, "Unexpected assembly after the end of the lambda"));
+    }
+
+    return Ok(lambda);
+}
+
+/// A decoded piece of code.
+enum Item {
+    /// An instruction, its operand, and the index of the next instruction.
+    Instruction(Opcode, Option<usize>, usize),
+    /// Bytes that don't make up a valid instruction.
+    Bytes(Vec<u8>),
+}
+
+struct Disassembler<'a> {
+    output: &'a mut String,
+    indent: usize,
+    /// Whether to annotate instructions with their source.
+    source: bool,
+    /// Where each line starts in the last source annotated,
+    /// so lines aren't counted from the start of the source for every instruction.
+    lines:  Option<(Rc<Source>, Vec<usize>)>,
+}
+
+impl<'a> Disassembler<'a> {
+    /// Writes a line at the current indentation.
+    fn line(&mut self, line: &str) {
+        for _ in 0..self.indent { self.output.push_str("    "); }
+        self.output.push_str(line);
+        self.output.push('\n');
+    }
+
+    /// Escapes a string so it can be read back by the assembler.
+    fn string(string: &str) -> String {
+        let mut escaped = String::from("\"");
+        for c in string.chars() {
+            match c {
+                '"'  => escaped.push_str("\\\""),
+                '\\' => escaped.push_str("\\\\"),
+                '\n' => escaped.push_str("\\n"),
+                '\t' => escaped.push_str("\\t"),
+                '\r' => escaped.push_str("\\r"),
+                c if c.is_control() => { write!(escaped, "\\u{{{:x}}}", c as u32).unwrap(); },
+                c => escaped.push(c),
+            }
+        }
+        escaped.push('"');
+        escaped
+    }
+
+    fn lambda(&mut self, lambda: &Lambda, comment: &str) {
+        self.line(&format!("lambda {{{}", comment));
+        self.indent += 1;
+
+        self.line("constants {");
+        self.indent += 1;
+        for (index, constant) in lambda.constants.iter().enumerate() {
+            self.data(constant, &format!(" -- {}", index));
+        }
+        self.indent -= 1;
+        self.line("}");
+
+        let captures = lambda.captures.iter().map(|captured| match captured {
+            Captured::Local(index)    => format!(" local {}", index),
+            Captured::Nonlocal(index) => format!(" nonlocal {}", index),
+        }).collect::<String>();
+        self.line(&format!("captures {{{} }}", captures));
+
+        self.line("code {");
+        self.indent += 1;
+        self.code(lambda);
+        self.indent -= 1;
+        self.line("}");
+
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    /// Writes a piece of data, nested data is written inline.
+    fn inline(data: &Data) -> Option<String> {
+        let written = match data {
+            Data::Unit       => "unit".to_string(),
+            Data::Real(n)    => format!("real {:?}", n),
+            Data::Boolean(b) => format!("boolean {}", b),
+            Data::String(s)  => format!("string {}", Disassembler::string(s)),
+            Data::Kind(k)    => format!("kind {}", Disassembler::string(k)),
+            // host functions are assembled by name, so unnamed ones can't be written
+            Data::Host(h)    => format!("host {}", Disassembler::string(h.name()?)),
+            Data::Label(kind, data) => format!(
+                "label {} {}", Disassembler::string(kind), Disassembler::inline(data)?,
+            ),
+            Data::Tuple(items) => format!("tuple {{ {}}}", Disassembler::items(items.iter())?),
+            Data::List(items)  => format!("list {{ {}}}", Disassembler::items(items.iter())?),
+            Data::Record(fields) => {
+                let mut written = String::from("record { ");
+                for (name, value) in fields.iter() {
+                    write!(written, "{} {} ", Disassembler::string(name), Disassembler::inline(value)?).unwrap();
+                }
+                written.push('}');
+                written
+            },
+            _ => return None,
+        };
+
+        Some(written)
+    }
+
+    fn items<'d>(items: impl Iterator<Item = &'d Data>) -> Option<String> {
+        items.map(|item| Disassembler::inline(item).map(|i| i + " ")).collect()
+    }
+
+    fn data(&mut self, data: &Data, comment: &str) {
+        match (data, Disassembler::inline(data)) {
+            (Data::Lambda(lambda), _) => self.lambda(lambda, comment),
+            (_, Some(written)) => self.line(&format!("{}{}", written, comment)),
+            // nothing else should be a constant
+            (other, None) => self.line(&format!("unknown -- {:?}", other)),
+        }
+    }
+
+    /// Splits code into instructions,
+    /// keeping bytes that can't be written as an instruction as they are.
+    fn decode(code: &[u8]) -> Vec<(usize, Item)> {
+        let mut items = vec![];
+        let mut index = 0;
+
+        while index < code.len() {
+            let item = Opcode::checked(code[index]).and_then(|opcode| {
+                if !opcode.has_operand() {
+                    return Some(Item::Instruction(opcode, None, index + 1));
+                }
+
+                let (operand, eaten) = try_build_number(&code[index + 1..])?;
+                let bytes = &code[index + 1..index + 1 + eaten];
+                // the operand must be written the way the assembler would write it
+                let encoded = if opcode.is_jump() { Lambda::pad_offset(operand) } else { split_number(operand) };
+                if bytes != encoded.as_slice() { return None; }
+
+                Some(Item::Instruction(opcode, Some(operand), index + 1 + eaten))
+            });
+
+            let item = item.unwrap_or_else(|| Item::Bytes(vec![code[index]]));
+            index = match &item {
+                Item::Instruction(_, _, next) => *next,
+                Item::Bytes(_) => index + 1,
+            };
+
+            // consecutive bytes are grouped together
+            match (items.last_mut(), item) {
+                (Some((_, Item::Bytes(bytes))), Item::Bytes(more)) => bytes.extend(more),
+                (_, item) => items.push((index, item)),
+            }
+        }
+
+        // items are recorded with where they start
+        let mut start = 0;
+        for (index, item) in items.iter_mut() {
+            let next = match item {
+                Item::Instruction(_, _, next) => *next,
+                Item::Bytes(bytes) => start + bytes.len(),
+            };
+            *index = start;
+            start = next;
+        }
+
+        items
+    }
+
+    /// Finds where a jump instruction lands.
+    fn target(opcode: Opcode, operand: usize, next: usize) -> Option<usize> {
+        match opcode {
+            Opcode::JumpBack => next.checked_sub(operand),
+            _ => next.checked_add(operand),
+        }
+    }
+
+    fn code(&mut self, lambda: &Lambda) {
+        let code  = &lambda.code;
+        let items = Disassembler::decode(code);
+        let mut line = None;
+        // spans apply to every instruction until the next span
+        let mut spans = lambda.spans.iter().peekable();
+        let mut span = None;
+
+        // jumps can only refer to labels at the start of instructions
+        let mut starts = items.iter()
+            .filter(|(_, item)| matches!(item, Item::Instruction(..)))
+            .map(|(index, _)| *index)
+            .collect::<HashSet<_>>();
+        starts.insert(code.len());
+
+        let labels = items.iter().filter_map(|(_, item)| match item {
+            Item::Instruction(opcode, Some(operand), next) if opcode.is_jump() => {
+                Disassembler::target(*opcode, *operand, *next).filter(|t| starts.contains(t))
+            },
+            _ => None,
+        }).collect::<HashSet<_>>();
+
+        for (index, item) in items.iter() {
+            if labels.contains(index) { self.label(*index); }
+            while let Some((_, next)) = spans.next_if(|(start, _)| start <= index) { span = Some(next); }
+            if let Some(span) = span.filter(|_| self.source) { self.source_line(span, &mut line); }
+
+            match item {
+                Item::Instruction(opcode, None, _) => self.line(&format!("{:?}", opcode)),
+                Item::Instruction(opcode, Some(operand), next) => {
+                    let target = Disassembler::target(*opcode, *operand, *next);
+                    match target.filter(|t| opcode.is_jump() && labels.contains(t)) {
+                        Some(target) => self.line(&format!("{:?} @{}", opcode, target)),
+                        None => self.line(&format!("{:?} {}", opcode, operand)),
+                    }
+                },
+                Item::Bytes(bytes) => {
+                    let bytes = bytes.iter().map(|b| format!("{} ", b)).collect::<String>();
+                    self.line(&format!("Bytes {{ {}}}", bytes));
+                },
+            }
+        }
+
+        if labels.contains(&code.len()) { self.label(code.len()); }
+    }
+
+    /// Writes the line of source a span lies on,
+    /// if it's not the same as the last line written.
+    fn source_line(&mut self, span: &Span, last: &mut Option<usize>) {
+        let source = match &span.source {
+            Some(source) => source,
+            None => return,
+        };
+
+        let cached = matches!(&self.lines, Some((lines, _)) if Rc::ptr_eq(lines, source));
+        if !cached {
+            let starts = std::iter::once(0)
+                .chain(source.contents.match_indices('\n').map(|(i, _)| i + 1))
+                .collect();
+            self.lines = Some((Rc::clone(source), starts));
+        }
+        let starts = &self.lines.as_ref().unwrap().1;
+
+        // lines are numbered from 1
+        let number = starts.partition_point(|start| *start <= span.offset);
+        if *last == Some(number) { return; }
+        *last = Some(number);
+
+        let start = starts[number - 1];
+        let end = starts.get(number).map_or(source.contents.len(), |next| next - 1);
+        let line = source.contents[start..end].trim().to_string();
+        self.line(&format!("-- {} | {}", number, line));
+    }
+
+    /// Labels are written outdented, so they stand out from the instructions.
+    fn label(&mut self, index: usize) {
+        self.indent -= 1;
+        self.line(&format!("@{}:", index));
+        self.indent += 1;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Word(String),
+    String(String),
+}
+
+/// The operand of an instruction being assembled.
+enum Operand {
+    None,
+    Number(usize),
+    Label(String),
+}
+
+/// A piece of code being assembled, and the line it's on.
+enum Piece {
+    Instruction(Opcode, Operand, usize),
+    Bytes(Vec<u8>),
+    Label(String, usize),
+}
+
+struct Assembler<'a> {
+    tokens: Vec<(Token, usize)>,
+    index:  usize,
+    ffi:    &'a FFI,
+}
+
+impl<'a> Assembler<'a> {
+    /// Splits assembly into tokens, each paired with its line.
+    fn lex(source: &str) -> Result<Vec<(Token, usize)>, AssemblyError> {
+        let mut tokens = vec![];
+        let mut chars  = source.chars().peekable();
+        let mut line   = 1;
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\n' => line += 1,
+                c if c.is_whitespace() => (),
+                '{' => tokens.push((Token::Open, line)),
+                '}' => tokens.push((Token::Close, line)),
+                '-' if chars.peek() == Some(&'-') => {
+                    while chars.peek().is_some_and(|c| *c != '\n') { chars.next(); }
+                },
+                '"' => {
+                    let start = line;
+                    let mut string = String::new();
+                    loop {
+                        let c = chars.next().ok_or_else(|| AssemblyError::new(start, "Unterminated string"))?;
+                        match c {
+                            '"' => break,
+                            '\\' => string.push(match chars.next() {
+                                Some('"')  => '"',
+                                Some('\\') => '\\',
+                                Some('n')  => '\n',
+                                Some('t')  => '\t',
+                                Some('r')  => '\r',
+                                Some('u')  => Assembler::unicode(&mut chars)
+                                    .ok_or_else(|| AssemblyError::new(line, "Invalid unicode escape"))?,
+                                _ => return Err(AssemblyError::new(line, "Unknown escape in string")),
+                            }),
+                            c => {
+                                if c == '\n' { line += 1; }
+                                string.push(c);
+                            },
+                        }
+                    }
+                    tokens.push((Token::String(string), start));
+                },
+                c => {
+                    let mut word = c.to_string();
+                    while let Some(c) = chars.peek().filter(|c| !c.is_whitespace() && !"{}\"".contains(**c)) {
+                        word.push(*c);
+                        chars.next();
+                    }
+                    tokens.push((Token::Word(word), line));
+                },
+            }
+        }
+
+        Ok(tokens)
+    }
+
+    /// Reads the `{XX}` of a `\u{XX}` escape.
+    fn unicode(chars: &mut impl Iterator<Item = char>) -> Option<char> {
+        if chars.next()? != '{' { return None; }
+        let digits = chars.take_while(|c| *c != '}').collect::<String>();
+        char::from_u32(u32::from_str_radix(&digits, 16).ok()?)
+    }
+
+    /// The line of the current token, or of the last token at the end.
+    fn line(&self) -> usize {
+        self.tokens.get(self.index.min(self.tokens.len().saturating_sub(1)))
+            .map_or(1, |(_, line)| *line)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, AssemblyError> {
+        Err(AssemblyError::new(self.line(), message))
+    }
+
+    fn next(&mut self) -> Result<Token, AssemblyError> {
+        match self.tokens.get(self.index) {
+            Some((token, _)) => { self.index += 1; Ok(token.clone()) },
+            None => self.error("Unexpected end of assembly"),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(token, _)| token)
+    }
+
+    fn word(&mut self) -> Result<String, AssemblyError> {
+        match self.next()? {
+            Token::Word(word) => Ok(word),
+            _ => { self.index -= 1; self.error("Expected a word") },
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), AssemblyError> {
+        match self.next()? {
+            Token::Word(word) if word == keyword => Ok(()),
+            _ => { self.index -= 1; self.error(&format!("Expected '{}'", keyword)) },
+        }
+    }
+
+    fn string(&mut self) -> Result<String, AssemblyError> {
+        match self.next()? {
+            Token::String(string) => Ok(string),
+            _ => { self.index -= 1; self.error("Expected a string") },
+        }
+    }
+
+    fn number(&mut self) -> Result<usize, AssemblyError> {
+        let word = self.word()?;
+        word.parse().or_else(|_| { self.index -= 1; self.error(&format!("Expected a number, found '{}'", word)) })
+    }
+
+    fn open(&mut self) -> Result<(), AssemblyError> {
+        match self.next()? {
+            Token::Open => Ok(()),
+            _ => { self.index -= 1; self.error("Expected '{'") },
+        }
+    }
+
+    /// Returns true and consumes the `}` if the block has ended.
+    fn close(&mut self) -> bool {
+        if self.peek() == Some(&Token::Close) { self.index += 1; true } else { false }
+    }
+
+    /// Parses the body of a `lambda { ... }`, after the `lambda` keyword.
+    fn lambda(&mut self) -> Result<Lambda, AssemblyError> {
+        let mut lambda = Lambda::empty();
+        self.open()?;
+
+        while !self.close() {
+            match self.word()?.as_str() {
+                "constants" => {
+                    self.open()?;
+                    while !self.close() { lambda.constants.push(self.data()?); }
+                },
+                "captures" => {
+                    self.open()?;
+                    while !self.close() {
+                        let captured = match self.word()?.as_str() {
+                            "local"    => Captured::Local(self.number()?),
+                            "nonlocal" => Captured::Nonlocal(self.number()?),
+                            other => { self.index -= 1; return self.error(&format!("Unknown capture '{}'", other)); },
+                        };
+                        lambda.captures.push(captured);
+                    }
+                },
+                "code" => {
+                    self.open()?;
+                    lambda.code = self.code()?;
+                },
+                other => { self.index -= 1; return self.error(&format!("Unknown section '{}'", other)); },
+            }
+        }
+
+        Ok(lambda)
+    }
+
+    fn items(&mut self) -> Result<Vec<Data>, AssemblyError> {
+        self.open()?;
+        let mut items = vec![];
+        while !self.close() { items.push(self.data()?); }
+        Ok(items)
+    }
+
+    fn data(&mut self) -> Result<Data, AssemblyError> {
+        let data = match self.word()?.as_str() {
+            "unit"    => Data::Unit,
+            "real"    => {
+                let word = self.word()?;
+                match word.parse() {
+                    Ok(n) => Data::Real(n),
+                    Err(_) => { self.index -= 1; return self.error(&format!("Expected a number, found '{}'", word)); },
+                }
+            },
+            "boolean" => match self.word()?.as_str() {
+                "true"  => Data::Boolean(true),
+                "false" => Data::Boolean(false),
+                _ => { self.index -= 1; return self.error("Expected 'true' or 'false'"); },
+            },
+            "string" => Data::String(self.string()?),
+            "kind"   => Data::Kind(self.string()?),
+            "label"  => Data::Label(Box::new(self.string()?), Box::new(self.data()?)),
+            "tuple"  => Data::Tuple(self.items()?),
+            "list"   => Data::List(self.items()?.into_iter().collect()),
+            "record" => {
+                self.open()?;
+                let mut fields = vec![];
+                while !self.close() { fields.push((self.string()?, self.data()?)); }
+                Data::Record(fields)
+            },
+            "lambda" => Data::Lambda(Box::new(self.lambda()?)),
+            "host"   => {
+                let name = self.string()?;
+                match self.ffi.get(&name) {
+                    Some(function) => Data::Host(function.clone()),
+                    None => { self.index -= 1; return self.error(&format!("The host function '{}' is not defined", name)); },
+                }
+            },
+            other => { self.index -= 1; return self.error(&format!("Unknown data '{}'", other)); },
+        };
+
+        Ok(data)
+    }
+
+    /// Looks up an opcode by name.
+    fn opcode(name: &str) -> Option<Opcode> {
+        (0..=u8::MAX).map_while(Opcode::checked).find(|opcode| format!("{:?}", opcode) == name)
+    }
+
+    /// Parses the instructions in a `code { ... }` block, then assembles them.
+    fn code(&mut self) -> Result<Vec<u8>, AssemblyError> {
+        let mut pieces = vec![];
+
+        while !self.close() {
+            let line = self.line();
+            let word = self.word()?;
+
+            if let Some(label) = word.strip_suffix(':') {
+                pieces.push(Piece::Label(label.to_string(), line));
+            } else if word == "Bytes" {
+                self.open()?;
+                let mut bytes = vec![];
+                while !self.close() {
+                    let byte = self.number()?;
+                    bytes.push(u8::try_from(byte).or_else(|_| self.error("Expected a byte"))?);
+                }
+                pieces.push(Piece::Bytes(bytes));
+            } else {
+                let opcode = match Assembler::opcode(&word) {
+                    Some(opcode) => opcode,
+                    None => { self.index -= 1; return self.error(&format!("Unknown opcode '{}'", word)); },
+                };
+
+                let operand = match self.peek() {
+                    _ if !opcode.has_operand() => Operand::None,
+                    Some(Token::Word(label)) if opcode.is_jump() && label.starts_with('@') => {
+                        Operand::Label(self.word()?)
+                    },
+                    _ => Operand::Number(self.number()?),
+                };
+                pieces.push(Piece::Instruction(opcode, operand, line));
+            }
+        }
+
+        // jumps are a fixed width, so labels can be placed before jumps are resolved
+        let mut labels = HashMap::new();
+        let mut index  = 0;
+        for piece in pieces.iter() {
+            match piece {
+                Piece::Label(label, line) => if labels.insert(format!("@{}", label.trim_start_matches('@')), index).is_some() {
+                    return Err(AssemblyError::new(*line, &format!("The label '{}' is defined twice", label)));
+                },
+                Piece::Bytes(bytes) => index += bytes.len(),
+                Piece::Instruction(opcode, operand, _) => index += 1 + match operand {
+                    Operand::None      => 0,
+                    _ if opcode.is_jump() => JUMP_WIDTH,
+                    Operand::Number(n) => split_number(*n).len(),
+                    Operand::Label(_)  => unreachable!("Only jumps refer to labels"),
+                },
+            }
+        }
+
+        let mut code = vec![];
+        for piece in pieces {
+            let (opcode, operand, line) = match piece {
+                Piece::Label(..) => continue,
+                Piece::Bytes(bytes) => { code.extend(bytes); continue; },
+                Piece::Instruction(opcode, operand, line) => (opcode, operand, line),
+            };
+
+            code.push(opcode as u8);
+            let operand = match operand {
+                Operand::None => continue,
+                Operand::Number(n) => n,
+                Operand::Label(label) => {
+                    let target = *labels.get(&label).ok_or_else(
+                        || AssemblyError::new(line, &format!("The label '{}' is not defined", label))
+                    )?;
+                    let next = code.len() + JUMP_WIDTH;
+                    let offset = if opcode == Opcode::JumpBack { next.checked_sub(target) } else { target.checked_sub(next) };
+                    offset.ok_or_else(|| AssemblyError::new(line, &format!("Can not jump that way to the label '{}'", label)))?
+                },
+            };
+
+            if !opcode.is_jump() {
+                code.extend(split_number(operand));
+            } else if split_number(operand).len() > JUMP_WIDTH {
+                return Err(AssemblyError::new(line, "The jump is too long"));
+            } else {
+                code.extend(Lambda::pad_offset(operand));
+            }
+        }
+
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::{
+        source::Source,
+        closure::Closure,
+        ffi::FFIFunction,
+    };
+    use crate::compiler::{lex, parse, desugar, gen::gen_with_ffi};
+    use crate::vm::vm::VM;
+
+    fn compile(source: &str, ffi: FFI) -> Lambda {
+        gen_with_ffi(desugar(parse(lex(Source::source(source)).unwrap()).unwrap()).unwrap(), ffi).unwrap()
+    }
+
+    /// Removes the spans from a lambda and its nested lambdas,
+    /// as they aren't kept by the assembly.
+    fn strip(lambda: &mut Lambda) {
+        lambda.spans.clear();
+        for constant in lambda.constants.iter_mut() {
+            if let Data::Lambda(nested) = constant { strip(nested); }
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut ffi = FFI::new();
+        ffi.add("double", FFIFunction::new(Ok));
+
+        let sources = [
+            "x = 1.0; y = x -> x + 1.0; y x",
+            "counter = n -> { step = x -> x + n; step n }; counter 2.0",
+            "p = Point { x: 0.5, y: [2.0 & []] }; Point { x, y: [h & t] } = p; (x, h, p.x)",
+            "f = m -> match m {\n Some v | v > 1.0 -> v\n None () -> 1.0\n }\n f (Some 2.0)",
+            "g = fiber { yield \"a \\\"quoted\\\"\\n\\tstring\"; 1.0 }; try { error (g ()) }",
+            "loop = x -> if x == 0.0 { () } else { loop (x - 1.0) }; double (loop 10.0)",
+        ];
+
+        for source in sources.iter() {
+            let mut lambda = compile(source, ffi.clone());
+            strip(&mut lambda);
+
+            let assembly = disassemble(&lambda);
+            assert_eq!(assemble_with_ffi(&assembly, &ffi), Ok(lambda), "{}", assembly);
+        }
+    }
+
+    #[test]
+    fn unnamed_host() {
+        let mut lambda = Lambda::empty();
+        lambda.constants.push(Data::Host(FFIFunction::new(Ok)));
+        lambda.constants.push(Data::Tuple(vec![Data::Host(FFIFunction::new(Ok))]));
+
+        let assembly = disassemble(&lambda);
+        assert_eq!(assembly.matches("unknown").count(), 2, "{}", assembly);
+        assert!(assemble(&assembly).is_err());
+        // lambdas are displayed as assembly
+        assert_eq!(format!("{}", lambda), assembly);
+    }
+
+    #[test]
+    fn source_annotations() {
+        let mut lambda = compile("x = 2.0\nsquare = n -> {\n    n * x\n}\nprint (square x)\n", FFI::new());
+        let annotated = disassemble_with_source(&lambda);
+
+        assert!(annotated.contains("    -- 1 | x = 2.0\n        Con 0\n"));
+        assert!(annotated.contains("-- 2 | square = n -> {\n        Capture 0\n"));
+        assert!(annotated.contains("-- 5 | print (square x)\n"));
+        // nested lambdas are annotated too
+        assert!(annotated.contains("-- 3 | n * x\n                Load 0\n"));
+
+        strip(&mut lambda);
+        assert_eq!(assemble(&annotated), Ok(lambda));
+    }
+
+    #[test]
+    fn raw_bytes() {
+        let mut lambda = Lambda::empty();
+        // not an opcode, an operand with a leading zero, and a cut off operand
+        lambda.code = vec![200, Opcode::Con as u8, 0, 128, Opcode::Del as u8, Opcode::Load as u8, 0];
+
+        let assembly = disassemble(&lambda);
+        assert!(assembly.contains("Bytes { 200 0 }\n        Con 0\n        Del\n        Bytes { 6 0 }\n"));
+        assert_eq!(assemble(&assembly), Ok(lambda));
+    }
+
+    #[test]
+    fn hand_written() {
+        // counts down from 3 to 0
+        let lambda = assemble("
+            lambda {
+                constants { real 3.0 real 1.0 real 0.0 }
+                captures { }
+                code {
+                    Con 0
+                @loop:
+                    Copy
+                    Con 2
+                    Equal
+                    Not
+                    JumpFalse @end  -- stop at zero
+                    Con 1
+                    Sub
+                    JumpBack @loop
+                @end:
+                }
+            }
+        ").unwrap();
+
+        let mut vm = VM::init();
+        assert_eq!(vm.eval(Closure::wrap(lambda)), Ok(Data::Real(0.0)));
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            ("lambda { code { Nope } }", 1, "Unknown opcode 'Nope'"),
+            ("lambda {\n code {\n Jump @end\n }\n }", 3, "The label '@end' is not defined"),
+            ("lambda { constants { real pi } }", 1, "Expected a number, found 'pi'"),
+            ("lambda { constants { host \"double\" } }", 1, "The host function 'double' is not defined"),
+            ("lambda { code { @a: @a: } }", 1, "The label '@a' is defined twice"),
+            ("lambda { code { } } extra", 1, "Unexpected assembly after the end of the lambda"),
+            ("lambda {\n constants {\n string \"open\n }", 3, "Unterminated string"),
+        ];
+
+        for (source, line, message) in cases.iter() {
+            let error = assemble(source).unwrap_err();
+            assert_eq!((error.line, error.message.as_str()), (*line, *message), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,600 @@
+use std::{
+    fmt,
+    rc::Rc,
+    path::PathBuf,
+    collections::HashMap,
+};
+
+use crate::common::{
+    data::Data,
+    list::List,
+    lambda::{Captured, Lambda},
+    span::Span,
+    source::Source,
+    number::{split_number, try_build_number},
+    ffi::FFI,
+    verify::{verify, VerifyError},
+};
+
+/// The first bytes of every bytecode file.
+pub const MAGIC: [u8; 4] = *b"PSRB";
+
+/// The version of the bytecode format written by `write`.
+/// This is bumped whenever the layout of the format
+/// or the meaning of any opcode changes;
+/// bytecode of any other version is rejected when read.
+pub const VERSION: u16 = 1;
+
+// tags for each kind of constant
+const UNIT:    u8 = 0;
+const REAL:    u8 = 1;
+const BOOLEAN: u8 = 2;
+const STRING:  u8 = 3;
+const KIND:    u8 = 4;
+const LAMBDA:  u8 = 5;
+const HOST:    u8 = 6;
+const LABEL:   u8 = 7;
+const TUPLE:   u8 = 8;
+const RECORD:  u8 = 9;
+const LIST:    u8 = 10;
+
+/// How deeply constants may be nested, i.e. lambdas within lambdas,
+/// so that reading malicious bytecode can't overflow the stack.
+const MAX_DEPTH: usize = 256;
+
+// tags for each kind of capture
+const LOCAL:    u8 = 0;
+const NONLOCAL: u8 = 1;
+
+/// Raised when a `Lambda` can not be written to or read from bytecode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BytecodeError {
+    /// The bytes don't start with `MAGIC`, so they aren't bytecode.
+    Magic,
+    /// The bytecode was written in a different version of the format.
+    Version(u16),
+    /// The bytecode ended before the `Lambda` was complete.
+    Truncated,
+    /// The bytecode is well-formed up to an offset, but not after it.
+    Malformed(usize, String),
+    /// The `Lambda` holds data that can't be written to bytecode.
+    Unsupported(String),
+    /// The bytecode calls a host function that isn't in the `FFI`.
+    Host(String),
+    /// The bytecode was read, but the `Lambda` it holds is malformed.
+    Invalid(VerifyError),
+}
+
+impl fmt::Display for BytecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BytecodeError::Magic => write!(f, "Not Passerine bytecode"),
+            BytecodeError::Version(v) => write!(
+                f, "Bytecode is version {}, but only version {} can be read", v, VERSION,
+            ),
+            BytecodeError::Truncated => write!(f, "Bytecode ended unexpectedly"),
+            BytecodeError::Malformed(offset, message) => write!(
+                f, "Malformed bytecode at byte {}: {}", offset, message,
+            ),
+            BytecodeError::Unsupported(message) => write!(f, "{}", message),
+            BytecodeError::Host(name) => write!(
+                f, "The host function '{}' is not defined", name,
+            ),
+            BytecodeError::Invalid(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// Writes a `Lambda`, and every `Lambda` nested in its constants, to bytecode.
+///
+/// The format is laid out as follows,
+/// where `n` is a number split with `split_number`,
+/// and `str` is the length of a UTF-8 string, as `n`, then its bytes:
+///
+/// ```plain
+/// file    := MAGIC VERSION(u16, little-endian) sources lambda
+/// sources := n (str:path str:contents)*
+/// lambda  := n:length code:u8* n constant* n capture* n span*
+/// span    := n:index n:source n:offset n:length
+/// capture := LOCAL n | NONLOCAL n
+/// ```
+///
+/// Each span refers to a source by its position in the source table, plus one;
+/// empty spans refer to the source `0`.
+/// The contents of each source are kept so errors can point at the code,
+/// even if the file the code came from is gone.
+///
+/// Each constant is a tag byte followed by the constant's data:
+/// a little-endian `f64` for numbers, a byte for booleans,
+/// `str`s for strings and labels, a nested `lambda` for functions,
+/// and a count followed by each item for tuples, records, and lists.
+/// Host functions are written by the name they were registered under,
+/// and are looked up in an `FFI` when read, see `read_with_ffi`.
+pub fn write(lambda: &Lambda) -> Result<Vec<u8>, BytecodeError> {
+    let mut writer = Writer { bytes: vec![], sources: vec![], indices: HashMap::new() };
+    writer.lambda(lambda)?;
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(VERSION.to_le_bytes());
+    let mut header = Writer { bytes, sources: vec![], indices: HashMap::new() };
+    header.number(writer.sources.len());
+    for source in writer.sources.iter() {
+        header.string(&source.path.to_string_lossy());
+        header.string(&source.contents);
+    }
+
+    header.bytes.append(&mut writer.bytes);
+    return Ok(header.bytes);
+}
+
+/// Reads a `Lambda` from bytecode written by `write`.
+/// Bytecode that calls host functions must be read with `read_with_ffi`.
+pub fn read(bytes: &[u8]) -> Result<Lambda, BytecodeError> {
+    read_with_ffi(bytes, &FFI::new())
+}
+
+/// Reads a `Lambda` from bytecode,
+/// resolving the host functions it calls by name in an `FFI`.
+/// The `Lambda` is checked with `verify`,
+/// so malformed bytecode is rejected rather than crashing the `VM`.
+pub fn read_with_ffi(bytes: &[u8], ffi: &FFI) -> Result<Lambda, BytecodeError> {
+    if !bytes.starts_with(&MAGIC) { return Err(BytecodeError::Magic); }
+    let mut reader = Reader { bytes, index: MAGIC.len(), depth: 0, sources: vec![], ffi };
+
+    let version = u16::from_le_bytes([reader.byte()?, reader.byte()?]);
+    if version != VERSION { return Err(BytecodeError::Version(version)); }
+
+    for _ in 0..reader.number()? {
+        let path     = PathBuf::from(reader.string()?);
+        let contents = reader.string()?;
+        reader.sources.push(Source::new(&contents, path));
+    }
+
+    let lambda = reader.lambda()?;
+    if reader.index != bytes.len() {
+        return Err(reader.malformed("Unexpected bytes after the end of the bytecode"));
+    }
+
+    verify(&lambda).map_err(BytecodeError::Invalid)?;
+    return Ok(lambda);
+}
+
+/// Writes the parts of a `Lambda` tree,
+/// collecting the sources its spans refer to along the way.
+struct Writer {
+    bytes:   Vec<u8>,
+    sources: Vec<Rc<Source>>,
+    /// Maps the address of each source to its position in `sources`.
+    indices: HashMap<usize, usize>,
+}
+
+impl Writer {
+    fn number(&mut self, number: usize) {
+        self.bytes.append(&mut split_number(number));
+    }
+
+    fn string(&mut self, string: &str) {
+        self.number(string.len());
+        self.bytes.extend(string.as_bytes());
+    }
+
+    /// Returns the number a span's source is referred to by.
+    fn source(&mut self, span: &Span) -> usize {
+        let source = match &span.source {
+            Some(source) => source,
+            None => return 0,
+        };
+
+        let sources = &mut self.sources;
+        let index = *self.indices.entry(Rc::as_ptr(source) as usize).or_insert_with(|| {
+            sources.push(Rc::clone(source));
+            sources.len() - 1
+        });
+
+        return index + 1;
+    }
+
+    fn lambda(&mut self, lambda: &Lambda) -> Result<(), BytecodeError> {
+        self.number(lambda.code.len());
+        self.bytes.extend(lambda.code.iter());
+
+        self.number(lambda.constants.len());
+        for constant in lambda.constants.iter() { self.data(constant)?; }
+
+        self.number(lambda.captures.len());
+        for capture in lambda.captures.iter() {
+            let (tag, index) = match capture {
+                Captured::Local(index)    => (LOCAL, index),
+                Captured::Nonlocal(index) => (NONLOCAL, index),
+            };
+            self.bytes.push(tag);
+            self.number(*index);
+        }
+
+        self.number(lambda.spans.len());
+        for (index, span) in lambda.spans.iter() {
+            self.number(*index);
+            let source = self.source(span);
+            self.number(source);
+            self.number(span.offset);
+            self.number(span.length);
+        }
+
+        Ok(())
+    }
+
+    fn data(&mut self, data: &Data) -> Result<(), BytecodeError> {
+        match data {
+            Data::Unit       => self.bytes.push(UNIT),
+            Data::Real(n)    => { self.bytes.push(REAL); self.bytes.extend(n.to_le_bytes()); },
+            Data::Boolean(b) => { self.bytes.push(BOOLEAN); self.bytes.push(*b as u8); },
+            Data::String(s)  => { self.bytes.push(STRING); self.string(s); },
+            Data::Kind(k)    => { self.bytes.push(KIND); self.string(k); },
+            Data::Lambda(l)  => { self.bytes.push(LAMBDA); self.lambda(l)?; },
+            Data::Host(h)    => match h.name() {
+                Some(name) => { self.bytes.push(HOST); self.string(name); },
+                None => return Err(BytecodeError::Unsupported(
+                    "Host functions must be registered in an FFI to be written to bytecode".to_string()
+                )),
+            },
+            Data::Label(kind, data) => {
+                self.bytes.push(LABEL);
+                self.string(kind);
+                self.data(data)?;
+            },
+            Data::Tuple(items) => {
+                self.bytes.push(TUPLE);
+                self.number(items.len());
+                for item in items.iter() { self.data(item)?; }
+            },
+            Data::Record(fields) => {
+                self.bytes.push(RECORD);
+                self.number(fields.len());
+                for (name, value) in fields.iter() {
+                    self.string(name);
+                    self.data(value)?;
+                }
+            },
+            Data::List(list) => {
+                self.bytes.push(LIST);
+                self.number(list.iter().count());
+                for item in list.iter() { self.data(item)?; }
+            },
+            other => return Err(BytecodeError::Unsupported(
+                format!("The data '{:?}' can not be written to bytecode", other)
+            )),
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the parts of a `Lambda` tree, checking that the bytecode is well-formed.
+struct Reader<'a> {
+    bytes:   &'a [u8],
+    index:   usize,
+    /// How many constants the constant being read is nested in.
+    depth:   usize,
+    sources: Vec<Rc<Source>>,
+    ffi:     &'a FFI,
+}
+
+impl<'a> Reader<'a> {
+    fn malformed(&self, message: &str) -> BytecodeError {
+        BytecodeError::Malformed(self.index, message.to_string())
+    }
+
+    fn byte(&mut self) -> Result<u8, BytecodeError> {
+        let byte = *self.bytes.get(self.index).ok_or(BytecodeError::Truncated)?;
+        self.index += 1;
+        Ok(byte)
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], BytecodeError> {
+        let end = self.index.checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(BytecodeError::Truncated)?;
+        let taken = &self.bytes[self.index..end];
+        self.index = end;
+        Ok(taken)
+    }
+
+    /// Reads a number split by `split_number`.
+    /// Unlike `build_number`, this checks that the number ends and fits in a `usize`.
+    fn number(&mut self) -> Result<usize, BytecodeError> {
+        let rest = &self.bytes[self.index..];
+        match try_build_number(rest) {
+            Some((number, eaten)) => { self.index += eaten; Ok(number) },
+            // the number ends, so it must not have fit
+            None if rest.iter().any(|byte| *byte >= 0b1000_0000) => Err(self.malformed("Number is too large")),
+            None => Err(BytecodeError::Truncated),
+        }
+    }
+
+    /// Reads a count of items, each at least one byte long.
+    fn count(&mut self) -> Result<usize, BytecodeError> {
+        let count = self.number()?;
+        if count > self.bytes.len() - self.index { return Err(BytecodeError::Truncated); }
+        Ok(count)
+    }
+
+    fn string(&mut self) -> Result<String, BytecodeError> {
+        let length = self.number()?;
+        let start  = self.index;
+        let bytes  = self.take(length)?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| BytecodeError::Malformed(start, "String is not valid UTF-8".to_string()))
+    }
+
+    fn lambda(&mut self) -> Result<Lambda, BytecodeError> {
+        let mut lambda = Lambda::empty();
+
+        let length  = self.number()?;
+        lambda.code = self.take(length)?.to_vec();
+
+        for _ in 0..self.count()? { lambda.constants.push(self.data()?); }
+
+        for _ in 0..self.count()? {
+            let capture = match self.byte()? {
+                LOCAL    => Captured::Local(self.number()?),
+                NONLOCAL => Captured::Nonlocal(self.number()?),
+                _ => { self.index -= 1; return Err(self.malformed("Unknown kind of capture")); },
+            };
+            lambda.captures.push(capture);
+        }
+
+        for _ in 0..self.count()? {
+            let index = self.number()?;
+            let start = self.index;
+            let span  = match self.number()? {
+                0 => { self.number()?; self.number()?; Span::empty() },
+                source => {
+                    let source = self.sources.get(source - 1).cloned().ok_or_else(
+                        || BytecodeError::Malformed(start, "Span refers to an unknown source".to_string())
+                    )?;
+                    let (offset, length) = (self.number()?, self.number()?);
+                    let end = offset.saturating_add(length);
+                    // a span outside of the source doesn't start or end on a boundary either
+                    if !(source.contents.is_char_boundary(offset) && source.contents.is_char_boundary(end)) {
+                        return Err(BytecodeError::Malformed(
+                            start, "Span does not start and end on characters in its source".to_string(),
+                        ));
+                    }
+                    Span::new(&source, offset, length)
+                },
+            };
+            lambda.spans.push((index, span));
+        }
+
+        Ok(lambda)
+    }
+
+    fn data(&mut self) -> Result<Data, BytecodeError> {
+        if self.depth == MAX_DEPTH { return Err(self.malformed("Constants are nested too deeply")); }
+        self.depth += 1;
+        let data = self.constant();
+        self.depth -= 1;
+        data
+    }
+
+    /// Reads a constant, which may contain more constants.
+    fn constant(&mut self) -> Result<Data, BytecodeError> {
+        let data = match self.byte()? {
+            UNIT    => Data::Unit,
+            REAL    => {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(self.take(8)?);
+                let real = f64::from_le_bytes(bytes);
+                // the vm stores pointers in the payload of NaNs,
+                // so a NaN must not carry a payload of its own
+                Data::Real(if real.is_nan() { f64::NAN } else { real })
+            },
+            BOOLEAN => match self.byte()? {
+                0 => Data::Boolean(false),
+                1 => Data::Boolean(true),
+                _ => { self.index -= 1; return Err(self.malformed("Boolean is neither true nor false")); },
+            },
+            STRING  => Data::String(self.string()?),
+            KIND    => Data::Kind(self.string()?),
+            LAMBDA  => Data::Lambda(Box::new(self.lambda()?)),
+            HOST    => {
+                let name = self.string()?;
+                match self.ffi.get(&name) {
+                    Some(function) => Data::Host(function.clone()),
+                    None => return Err(BytecodeError::Host(name)),
+                }
+            },
+            LABEL   => Data::Label(Box::new(self.string()?), Box::new(self.data()?)),
+            TUPLE   => Data::Tuple(
+                (0..self.count()?).map(|_| self.data()).collect::<Result<_, _>>()?
+            ),
+            RECORD  => Data::Record(
+                (0..self.count()?).map(|_| Ok((self.string()?, self.data()?))).collect::<Result<_, _>>()?
+            ),
+            LIST    => Data::List(
+                (0..self.count()?).map(|_| self.data()).collect::<Result<List, _>>()?
+            ),
+            _ => { self.index -= 1; return Err(self.malformed("Unknown kind of constant")); },
+        };
+
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::{
+        ffi::FFIFunction,
+        opcode::Opcode,
+        verify::Reason,
+        closure::Closure,
+    };
+    use crate::vm::vm::VM;
+    use crate::compiler::{lex, parse, desugar, gen::gen_with_ffi};
+
+    fn compile(source: &str, ffi: FFI) -> Lambda {
+        let source = Source::new(source, PathBuf::from("./test.pn"));
+        gen_with_ffi(desugar(parse(lex(source).unwrap()).unwrap()).unwrap(), ffi).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let lambda = compile("
+            greeting = \"Hello\"
+            counter = n -> { step = x -> x + 1.0; step n }
+            pair = (true, Point { x: 1.5, y: [()] })
+            print (counter 2.0, pair, greeting)
+        ", FFI::new());
+
+        let bytes = write(&lambda).unwrap();
+        assert!(bytes.starts_with(&MAGIC));
+        let read_back = read(&bytes).unwrap();
+        assert_eq!(read_back, lambda);
+
+        // spans still point into the source they came from
+        let span = read_back.spans.last().unwrap().1.clone();
+        assert_eq!(span.source.as_ref().unwrap().path, PathBuf::from("./test.pn"));
+        assert_eq!(span.contents(), "print (counter 2.0, pair, greeting)");
+    }
+
+    #[test]
+    fn host_functions() {
+        let mut ffi = FFI::new();
+        ffi.add("double", FFIFunction::new(Ok));
+        let lambda = compile("double 1.0", ffi.clone());
+        let bytes  = write(&lambda).unwrap();
+
+        assert_eq!(read(&bytes), Err(BytecodeError::Host("double".to_string())));
+        assert_eq!(read_with_ffi(&bytes, &ffi), Ok(lambda));
+
+        let mut unnamed = Lambda::empty();
+        unnamed.constants.push(Data::Host(FFIFunction::new(Ok)));
+        assert!(matches!(write(&unnamed), Err(BytecodeError::Unsupported(_))));
+    }
+
+    #[test]
+    fn canonical_nans() {
+        // a NaN whose bits look like a pointer to the vm
+        let mut lambda = Lambda::empty();
+        lambda.emit(Opcode::Con);
+        lambda.emit_bytes(&mut split_number(0));
+        lambda.constants.push(Data::Real(f64::from_bits(0xFFFE_0000_DEAD_BEE8)));
+
+        let read_back = read(&write(&lambda).unwrap()).unwrap();
+        match &read_back.constants[0] {
+            Data::Real(n) => assert_eq!(n.to_bits(), f64::NAN.to_bits()),
+            other => panic!("Expected a real, found {:?}", other),
+        }
+
+        match VM::init().eval(Closure::wrap(read_back)) {
+            Ok(Data::Real(n)) => assert!(n.is_nan()),
+            other => panic!("Expected NaN, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_bad_bytecode() {
+        let bytes = write(&compile("x = 1.0; x", FFI::new())).unwrap();
+
+        assert_eq!(read(b"not bytecode"), Err(BytecodeError::Magic));
+
+        let mut newer = bytes.clone();
+        newer[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(read(&newer), Err(BytecodeError::Version(VERSION + 1)));
+
+        for end in 0..bytes.len() {
+            assert!(read(&bytes[..end]).is_err());
+        }
+
+        let mut extra = bytes;
+        extra.push(0);
+        assert!(matches!(read(&extra), Err(BytecodeError::Malformed(_, _))));
+    }
+
+    #[test]
+    fn rejects_malformed_lambdas() {
+        let mut lambda = compile("x = 1.0; x", FFI::new());
+        lambda.code.push(Opcode::Del as u8);
+        lambda.code.push(Opcode::Del as u8);
+        lambda.code.push(Opcode::Del as u8);
+
+        match read(&write(&lambda).unwrap()) {
+            Err(BytecodeError::Invalid(error)) => assert_eq!(error.reason, Reason::Underflow),
+            other => panic!("Expected the lambda to be invalid, found {:?}", other),
+        }
+
+        // the top level of a script has no frame to return from,
+        // so running either would crash the vm
+        let mut returns = compile("x = 1.0; x", FFI::new());
+        returns.emit(Opcode::Return);
+        returns.emit_bytes(&mut split_number(1));
+
+        let mut tail_calls = compile("f = x -> x; a = 1.0; ()", FFI::new());
+        tail_calls.emit(Opcode::Del);
+        for local in [1, 0].iter() {
+            tail_calls.emit(Opcode::Load);
+            tail_calls.emit_bytes(&mut split_number(*local));
+        }
+        tail_calls.emit(Opcode::TailCall);
+
+        for lambda in [returns, tail_calls].iter() {
+            match read(&write(lambda).unwrap()) {
+                Err(BytecodeError::Invalid(error)) => assert_eq!(error.reason, Reason::Script),
+                Ok(lambda) => panic!("Expected the lambda to be invalid, ran {:?}", VM::init().run(Closure::wrap(lambda))),
+                other => panic!("Expected the lambda to be invalid, found {:?}", other),
+            }
+        }
+    }
+
+    /// Bytecode for a lambda with no code and a single constant.
+    fn with_constant(constant: &[u8]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend(split_number(0)); // sources
+        bytes.extend(split_number(0)); // code
+        bytes.extend(split_number(1)); // constants
+        bytes.extend(constant);
+        bytes.extend(split_number(0)); // captures
+        bytes.extend(split_number(0)); // spans
+        bytes
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        let mut labels = vec![];
+        for _ in 0..1_000_000 { labels.push(LABEL); labels.extend(split_number(0)); }
+        labels.push(UNIT);
+        assert!(matches!(read(&with_constant(&labels)), Err(BytecodeError::Malformed(_, _))));
+
+        // nesting within the limit is fine
+        let mut labels = vec![];
+        for _ in 0..MAX_DEPTH - 1 { labels.push(LABEL); labels.extend(split_number(0)); }
+        labels.push(UNIT);
+        assert!(read(&with_constant(&labels)).is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_numbers() {
+        // a string whose length doesn't fit in a usize
+        let mut string = vec![STRING];
+        string.extend(vec![0b0111_1111; 20]);
+        string.push(0b1000_0000);
+        assert!(matches!(read(&with_constant(&string)), Err(BytecodeError::Malformed(_, _))));
+    }
+
+    #[test]
+    fn rejects_split_characters() {
+        let lambda = compile("\"é\"", FFI::new());
+        let mut bytes = write(&lambda).unwrap();
+
+        // the last span covers the whole source, so make it end within the 'é'
+        let length = split_number(lambda.spans.last().unwrap().1.length);
+        let end = bytes.len() - length.len();
+        assert_eq!(bytes[end..], length[..]);
+        bytes.truncate(end);
+        bytes.extend(split_number(2));
+
+        assert!(matches!(read(&bytes), Err(BytecodeError::Malformed(_, _))));
+    }
+}
@@ -0,0 +1,268 @@
+use std::fmt;
+
+use crate::common::data::Data;
+
+/// Raised when `Data` can not be converted to a Rust value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversionError {
+    /// A description of the data that was expected, i.e. `"a number"`.
+    pub expected: String,
+    /// The data that was found instead.
+    pub found: String,
+}
+
+impl ConversionError {
+    /// Creates a new `ConversionError`.
+    pub fn new(expected: &str, found: &Data) -> ConversionError {
+        ConversionError {
+            expected: expected.to_string(),
+            found:    found.describe(),
+        }
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Expected {}, found the data '{}'", self.expected, self.found)
+    }
+}
+
+/// Converts a Rust value into Passerine `Data`.
+pub trait IntoData {
+    fn into_data(self) -> Data;
+}
+
+/// Converts Passerine `Data` into a Rust value,
+/// raising a `ConversionError` if the data is of the wrong shape.
+pub trait FromData: Sized {
+    fn from_data(data: Data) -> Result<Self, ConversionError>;
+}
+
+impl IntoData for Data {
+    fn into_data(self) -> Data { self }
+}
+
+impl FromData for Data {
+    fn from_data(data: Data) -> Result<Data, ConversionError> { Ok(data) }
+}
+
+impl IntoData for f64 {
+    fn into_data(self) -> Data { Data::Real(self) }
+}
+
+impl FromData for f64 {
+    fn from_data(data: Data) -> Result<f64, ConversionError> {
+        match data {
+            Data::Real(n) => Ok(n),
+            other => Err(ConversionError::new("a number", &other)),
+        }
+    }
+}
+
+impl IntoData for bool {
+    fn into_data(self) -> Data { Data::Boolean(self) }
+}
+
+impl FromData for bool {
+    fn from_data(data: Data) -> Result<bool, ConversionError> {
+        match data {
+            Data::Boolean(b) => Ok(b),
+            other => Err(ConversionError::new("a boolean", &other)),
+        }
+    }
+}
+
+impl IntoData for String {
+    fn into_data(self) -> Data { Data::String(self) }
+}
+
+impl IntoData for &str {
+    fn into_data(self) -> Data { Data::String(self.to_string()) }
+}
+
+impl FromData for String {
+    fn from_data(data: Data) -> Result<String, ConversionError> {
+        match data {
+            Data::String(s) => Ok(s),
+            other => Err(ConversionError::new("a string", &other)),
+        }
+    }
+}
+
+impl IntoData for () {
+    fn into_data(self) -> Data { Data::Unit }
+}
+
+impl FromData for () {
+    fn from_data(data: Data) -> Result<(), ConversionError> {
+        match data {
+            Data::Unit => Ok(()),
+            other => Err(ConversionError::new("the Unit, '()'", &other)),
+        }
+    }
+}
+
+/// `Vec`s are converted to lists.
+impl<T: IntoData> IntoData for Vec<T> {
+    fn into_data(self) -> Data {
+        Data::List(self.into_iter().map(IntoData::into_data).collect())
+    }
+}
+
+impl<T: FromData> FromData for Vec<T> {
+    fn from_data(data: Data) -> Result<Vec<T>, ConversionError> {
+        match data {
+            Data::List(l) => l.iter().cloned().map(T::from_data).collect(),
+            other => Err(ConversionError::new("a list", &other)),
+        }
+    }
+}
+
+/// Unwraps the data in a label with a given name.
+fn unlabel(data: Data, name: &str, expected: &str) -> Result<Data, ConversionError> {
+    match data {
+        Data::Label(n, d) if *n == name => Ok(*d),
+        other => Err(ConversionError::new(expected, &other)),
+    }
+}
+
+/// `Option`s are converted to the labels `Option.Some value` and `Option.None ()`.
+impl<T: IntoData> IntoData for Option<T> {
+    fn into_data(self) -> Data {
+        match self {
+            Some(data) => Data::Label(Box::new("Option.Some".to_string()), Box::new(data.into_data())),
+            None       => Data::Label(Box::new("Option.None".to_string()), Box::new(Data::Unit)),
+        }
+    }
+}
+
+impl<T: FromData> FromData for Option<T> {
+    fn from_data(data: Data) -> Result<Option<T>, ConversionError> {
+        match data {
+            Data::Label(n, d) if *n == "Option.Some" => Ok(Some(T::from_data(*d)?)),
+            other => unlabel(other, "Option.None", "an Option").and_then(<()>::from_data).map(|_| None),
+        }
+    }
+}
+
+/// `Result`s are converted to the labels `Result.Ok value` and `Result.Error error`,
+/// the same labels a `try` evaluates to.
+impl<T: IntoData, E: IntoData> IntoData for Result<T, E> {
+    fn into_data(self) -> Data {
+        match self {
+            Ok(data)   => Data::Label(Box::new("Result.Ok".to_string()),    Box::new(data.into_data())),
+            Err(error) => Data::Label(Box::new("Result.Error".to_string()), Box::new(error.into_data())),
+        }
+    }
+}
+
+impl<T: FromData, E: FromData> FromData for Result<T, E> {
+    fn from_data(data: Data) -> Result<Result<T, E>, ConversionError> {
+        match data {
+            Data::Label(n, d) if *n == "Result.Ok" => Ok(Ok(T::from_data(*d)?)),
+            other => Ok(Err(E::from_data(unlabel(other, "Result.Error", "a Result")?)?)),
+        }
+    }
+}
+
+/// Removes a field from a record and converts it,
+/// used by `#[derive(PasserineData)]` to convert records to structs.
+pub fn take_field<T: FromData>(
+    fields: &mut Vec<(String, Data)>,
+    name:   &str,
+) -> Result<T, ConversionError> {
+    match fields.iter().position(|(n, _)| n == name) {
+        Some(index) => T::from_data(fields.remove(index).1),
+        None => Err(ConversionError::new(
+            &format!("a record with the field '{}'", name),
+            &Data::Record(fields.clone()),
+        )),
+    }
+}
+
+/// Implements the conversion traits for tuples of a given size.
+macro_rules! tuple {
+    ($size:literal; $($item:ident),+) => {
+        impl<$($item: IntoData),+> IntoData for ($($item,)+) {
+            #[allow(non_snake_case)]
+            fn into_data(self) -> Data {
+                let ($($item,)+) = self;
+                Data::Tuple(vec![$($item.into_data()),+])
+            }
+        }
+
+        impl<$($item: FromData),+> FromData for ($($item,)+) {
+            fn from_data(data: Data) -> Result<($($item,)+), ConversionError> {
+                match data {
+                    Data::Tuple(t) if t.len() == $size => {
+                        let mut items = t.into_iter();
+                        Ok(($($item::from_data(items.next().unwrap())?,)+))
+                    },
+                    other => Err(ConversionError::new(
+                        concat!("a tuple of ", $size, " items"), &other,
+                    )),
+                }
+            }
+        }
+    };
+}
+
+tuple!(2; A, B);
+tuple!(3; A, B, C);
+tuple!(4; A, B, C, D);
+tuple!(5; A, B, C, D, E);
+tuple!(6; A, B, C, D, E, F);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip<T: IntoData + FromData + Clone + PartialEq + fmt::Debug>(value: T) {
+        assert_eq!(T::from_data(value.clone().into_data()), Ok(value));
+    }
+
+    #[test]
+    fn atoms() {
+        round_trip(4.2);
+        round_trip(true);
+        round_trip("Hello".to_string());
+        round_trip(());
+    }
+
+    #[test]
+    fn compound() {
+        round_trip(vec![1.0, 2.0, 3.0]);
+        round_trip(Some(vec![true]));
+        round_trip::<Option<f64>>(None);
+        round_trip::<Result<f64, String>>(Ok(1.0));
+        round_trip::<Result<f64, String>>(Err("nope".to_string()));
+        round_trip((1.0, "two".to_string(), (false, ())));
+    }
+
+    #[test]
+    fn shape() {
+        assert_eq!(
+            (Some(1.0), vec!["a"]).into_data(),
+            Data::Tuple(vec![
+                Data::Label(Box::new("Option.Some".to_string()), Box::new(Data::Real(1.0))),
+                Data::List(vec![Data::String("a".to_string())].into_iter().collect()),
+            ]),
+        );
+    }
+
+    #[test]
+    fn mismatch() {
+        let error = f64::from_data(Data::Boolean(true)).unwrap_err();
+        assert_eq!(format!("{}", error), "Expected a number, found the data 'true'");
+
+        let error = <(f64, f64)>::from_data(Data::Tuple(vec![Data::Real(1.0)])).unwrap_err();
+        assert_eq!(error.expected, "a tuple of 2 items");
+
+        let error = Vec::<f64>::from_data(Data::List(vec![Data::Unit].into_iter().collect()));
+        assert_eq!(error, Err(ConversionError::new("a number", &Data::Unit)));
+
+        // naked labels can't be displayed, so they're described instead
+        let error = f64::from_data(Data::Kind("None".to_string())).unwrap_err();
+        assert_eq!(error.found, "Kind(None)");
+    }
+}
@@ -13,6 +13,9 @@ use std::{
 use crate::common::{
     lambda::Lambda,
     closure::Closure,
+    list::List,
+    ffi::FFIFunction,
+    fiber::FiberRef,
 };
 
 /// Built-in Passerine datatypes.
@@ -30,6 +33,9 @@ pub enum Data {
     // TODO: make lambda Rc?
     Lambda(Box<Lambda>),
     Closure(Box<Closure>),
+    Fiber(FiberRef),
+    /// A function implemented in Rust, see `FFI`.
+    Host(FFIFunction),
 
     // TODO: rework how labels and tags work
     // Kind is the base component of an unconstructed label
@@ -38,14 +44,41 @@ pub enum Data {
 
     // Compound Datatypes
     Unit, // an empty typle
-    // Tuple(Vec<Data>),
-    // // TODO: Hashmap?
-    // // I mean, it's overkill for small things
-    // // yet if people have very big records, yk.
-    // Record(Vec<(Local, Data)>),
+    Tuple(Vec<Data>),
+    // TODO: Hashmap?
+    // I mean, it's overkill for small things
+    // yet if people have very big records, yk.
+    /// Fields are kept sorted by name, so records can be compared.
+    Record(Vec<(String, Data)>),
+    List(List),
     // ArbInt(ArbInt),
 }
 
+impl Data {
+    /// Whether data can be formatted with `Display`.
+    /// Data only used internally by the VM, like frames or naked labels, can not be.
+    pub fn displayable(&self) -> bool {
+        match self {
+              Data::Frame
+            | Data::NotInit
+            | Data::Heaped(_)
+            | Data::Lambda(_)
+            | Data::Kind(_) => false,
+            Data::Label(_, v) => v.displayable(),
+            Data::Tuple(t)    => t.iter().all(Data::displayable),
+            Data::Record(r)   => r.iter().all(|(_, v)| v.displayable()),
+            Data::List(l)     => l.iter().all(Data::displayable),
+            _ => true,
+        }
+    }
+
+    /// Formats data for an error message,
+    /// falling back to `Debug` for data that is not displayable.
+    pub fn describe(&self) -> String {
+        if self.displayable() { format!("{}", self) } else { format!("{:?}", self) }
+    }
+}
+
 // TODO: manually implement the equality trait
 // NOTE: might have to implement partial equality as well
 // NOTE: equality represents passerine equality, not rust equality
@@ -62,9 +95,20 @@ impl Display for Data {
             Data::String(s)   => write!(f, "{}", s),
             Data::Lambda(_)   => unreachable!("Can not display naked functions"),
             Data::Closure(c)  => write!(f, "Function ~ {}", c.id),
+            Data::Fiber(_)    => write!(f, "Fiber"),
+            Data::Host(_)     => write!(f, "Host Function"),
             Data::Kind(_)     => unreachable!("Can not display naked labels"),
             Data::Label(n, v) => write!(f, "{} {}", n, v),
             Data::Unit        => write!(f, "()"),
+            Data::Tuple(t)    => write!(f, "({})",
+                t.iter().map(|i| format!("{}", i)).collect::<Vec<String>>().join(", ")
+            ),
+            Data::Record(r)   => write!(f, "{{ {} }}",
+                r.iter().map(|(n, v)| format!("{}: {}", n, v)).collect::<Vec<String>>().join(", ")
+            ),
+            Data::List(l)     => write!(f, "[{}]",
+                l.iter().map(|i| format!("{}", i)).collect::<Vec<String>>().join(", ")
+            ),
         }
     }
 }
@@ -80,9 +124,14 @@ impl Debug for Data {
             Data::String(s)   => write!(f, "String({:?})", s),
             Data::Lambda(_)   => write!(f, "Function(...)"),
             Data::Closure(c)  => write!(f, "Closure({})", c.id),
+            Data::Fiber(r)    => write!(f, "{:?}", r),
+            Data::Host(h)     => write!(f, "{:?}", h),
             Data::Kind(n)     => write!(f, "Kind({})", n),
             Data::Label(n, v) => write!(f, "Label({}, {:?})", n, v),
             Data::Unit        => write!(f, "Unit"),
+            Data::Tuple(t)    => write!(f, "Tuple({:?})", t),
+            Data::Record(r)   => write!(f, "Record({:?})", r),
+            Data::List(l)     => write!(f, "List({:?})", l.iter().collect::<Vec<&Data>>()),
         }
     }
 }
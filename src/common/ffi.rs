@@ -0,0 +1,124 @@
+use std::{
+    fmt,
+    rc::Rc,
+    collections::HashMap,
+};
+
+use crate::common::{
+    data::Data,
+    convert::{IntoData, FromData},
+};
+
+/// A Rust function that can be called from Passerine.
+/// Host functions take a single argument and return a value,
+/// just like any other Passerine function.
+/// If a host function returns an `Err`,
+/// the message is raised as an error at the call site.
+#[derive(Clone)]
+pub struct FFIFunction {
+    /// The name the function was registered under in an `FFI`, if any.
+    name:     Option<Rc<str>>,
+    function: Rc<dyn Fn(Data) -> Result<Data, String>>,
+}
+
+impl FFIFunction {
+    /// Wraps a Rust closure so it can be called from Passerine.
+    pub fn new(function: impl Fn(Data) -> Result<Data, String> + 'static) -> FFIFunction {
+        FFIFunction { name: None, function: Rc::new(function) }
+    }
+
+    /// Wraps a Rust closure that takes and returns plain Rust types,
+    /// converting the argument from and the result into `Data`.
+    /// Data that can not be converted is raised as an error.
+    pub fn typed<A, R>(function: impl Fn(A) -> Result<R, String> + 'static) -> FFIFunction
+    where A: FromData, R: IntoData {
+        FFIFunction::new(move |data| {
+            let arg = A::from_data(data).map_err(|e| e.to_string())?;
+            function(arg).map(IntoData::into_data)
+        })
+    }
+
+    /// Calls the wrapped function.
+    pub fn call(&self, data: Data) -> Result<Data, String> {
+        (self.function)(data)
+    }
+
+    /// The name the function was registered under,
+    /// used to refer to the function in compiled bytecode.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+impl PartialEq for FFIFunction {
+    fn eq(&self, other: &FFIFunction) -> bool {
+        Rc::ptr_eq(&self.function, &other.function)
+    }
+}
+
+impl fmt::Debug for FFIFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FFIFunction")
+    }
+}
+
+/// A registry of named host functions,
+/// passed to the compiler when generating bytecode.
+/// Variables that aren't defined in a script are looked up in the `FFI`,
+/// so host functions can be called like any other function.
+#[derive(Debug, Clone, Default)]
+pub struct FFI(HashMap<String, FFIFunction>);
+
+impl FFI {
+    /// Creates a new empty `FFI`.
+    pub fn new() -> FFI {
+        FFI(HashMap::new())
+    }
+
+    /// Registers a host function under a name,
+    /// returning the function previously registered under that name, if any.
+    pub fn add(&mut self, name: &str, function: FFIFunction) -> Option<FFIFunction> {
+        let function = FFIFunction { name: Some(name.into()), ..function };
+        self.0.insert(name.to_string(), function)
+    }
+
+    /// Looks up a host function by name.
+    pub fn get(&self, name: &str) -> Option<&FFIFunction> {
+        self.0.get(name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn add_and_call() {
+        let mut ffi = FFI::new();
+        let double = FFIFunction::new(|data| match data {
+            Data::Real(n) => Ok(Data::Real(n * 2.0)),
+            _ => Err("Expected a number".to_string()),
+        });
+
+        assert!(ffi.add("double", double.clone()).is_none());
+        assert_eq!(ffi.add("double", double.clone()), Some(double));
+
+        let function = ffi.get("double").unwrap();
+        assert_eq!(function.name(), Some("double"));
+        assert_eq!(function.call(Data::Real(2.0)), Ok(Data::Real(4.0)));
+        assert!(function.call(Data::Unit).is_err());
+        assert!(ffi.get("triple").is_none());
+    }
+
+    #[test]
+    fn typed() {
+        let add = FFIFunction::typed(|(a, b): (f64, f64)| Ok(a + b));
+        let pair = Data::Tuple(vec![Data::Real(1.0), Data::Real(2.0)]);
+
+        assert_eq!(add.call(pair), Ok(Data::Real(3.0)));
+        assert_eq!(
+            add.call(Data::Real(1.0)),
+            Err("Expected a tuple of 2 items, found the data '1'".to_string()),
+        );
+    }
+}
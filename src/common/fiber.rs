@@ -0,0 +1,88 @@
+use std::{
+    any::Any,
+    fmt,
+    rc::Rc,
+    cell::{Ref, RefCell, RefMut},
+};
+
+/// Whether a fiber can be resumed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// The fiber has not been started, or has yielded.
+    Suspended,
+    /// The fiber is running, or has resumed another fiber.
+    Running,
+    /// The fiber has returned, or crashed.
+    Done,
+}
+
+/// The state of a fiber, which only the `vm` knows how to run.
+/// Outside of the vm, the state is opaque, save for its status.
+pub trait FiberState: Any {
+    /// Whether the fiber can be resumed.
+    fn status(&self) -> Status;
+    /// Used to downcast the state back to the vm's representation.
+    fn as_any(&self) -> &dyn Any;
+    /// Used to downcast the state back to the vm's representation.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+/// A reference to a fiber, as stored in `Data`.
+/// Fibers are mutable, so every reference to a fiber
+/// sees it advance when it's resumed.
+/// Two references are only equal if they refer to the same fiber.
+#[derive(Clone)]
+pub struct FiberRef(Rc<RefCell<dyn FiberState>>);
+
+impl FiberRef {
+    /// Wraps the state of a fiber so it can be shared.
+    pub fn new<S: FiberState>(state: S) -> FiberRef {
+        FiberRef(Rc::new(RefCell::new(state)))
+    }
+
+    /// The address of the fiber, which identifies it.
+    pub fn address(&self) -> usize {
+        Rc::as_ptr(&self.0) as *const () as usize
+    }
+
+    /// Borrows the fiber's state.
+    /// Panics if the state is already mutably borrowed, or isn't an `S`.
+    pub fn borrow<S: FiberState>(&self) -> Ref<'_, S> {
+        Ref::map(self.0.borrow(), FiberRef::downcast)
+    }
+
+    /// Mutably borrows the fiber's state.
+    /// Panics if the state is already borrowed, or isn't an `S`.
+    pub fn borrow_mut<S: FiberState>(&self) -> RefMut<'_, S> {
+        RefMut::map(self.0.borrow_mut(), |state| {
+            state.as_any_mut().downcast_mut().expect("Fiber state is of the wrong type")
+        })
+    }
+
+    /// Borrows the fiber's state,
+    /// returning `None` if the state is already mutably borrowed.
+    /// Panics if the state isn't an `S`.
+    pub fn try_borrow<S: FiberState>(&self) -> Option<Ref<'_, S>> {
+        self.0.try_borrow().ok().map(|state| Ref::map(state, FiberRef::downcast))
+    }
+
+    fn downcast<S: FiberState>(state: &dyn FiberState) -> &S {
+        state.as_any().downcast_ref().expect("Fiber state is of the wrong type")
+    }
+}
+
+impl PartialEq for FiberRef {
+    fn eq(&self, other: &FiberRef) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for FiberRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // a fiber may be borrowed while it's running
+        match self.0.try_borrow() {
+            Ok(state) => write!(f, "Fiber({:?})", state.status()),
+            Err(_)    => write!(f, "Fiber(Running)"),
+        }
+    }
+}
@@ -1,12 +1,20 @@
 use crate::common::{
     opcode::Opcode,
     data::Data,
-    number::build_number,
+    number::split_number,
     span::Span,
+    assembly::disassemble,
 };
 
 use std::fmt;
 
+/// The number of bytes reserved for the offset of a jump.
+/// Offsets are padded to a fixed width so they can be patched in place
+/// once the jump target is known.
+/// Leading zero bytes don't change the value of a split number,
+/// so padded offsets can still be read with `build_number`.
+pub const JUMP_WIDTH: usize = 4;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Captured {
     /// The index on the stack if the variable is local to the current scope
@@ -59,6 +67,42 @@ impl Lambda {
         self.spans.push((self.code.len(), span.clone()))
     }
 
+    /// Emits a forward jump whose offset is not yet known,
+    /// returning the index of the offset so it can be patched later.
+    /// See `patch_jump`.
+    pub fn emit_jump(&mut self, op: Opcode) -> usize {
+        self.emit(op);
+        let index = self.code.len();
+        self.code.extend(Lambda::pad_offset(0));
+        return index;
+    }
+
+    /// Patches a forward jump emitted by `emit_jump`
+    /// to jump to the end of the currently emitted code.
+    pub fn patch_jump(&mut self, index: usize) {
+        let offset = self.code.len() - (index + JUMP_WIDTH);
+        self.code.splice(index..index + JUMP_WIDTH, Lambda::pad_offset(offset));
+    }
+
+    /// Emits a backward jump to an earlier index in the code.
+    pub fn emit_jump_back(&mut self, target: usize) {
+        self.emit(Opcode::JumpBack);
+        let offset = self.code.len() + JUMP_WIDTH - target;
+        self.code.extend(Lambda::pad_offset(offset));
+    }
+
+    /// Splits a jump offset into exactly `JUMP_WIDTH` bytes.
+    pub fn pad_offset(offset: usize) -> Vec<u8> {
+        let split = split_number(offset);
+        if split.len() > JUMP_WIDTH {
+            panic!("Can not jump {} bytes, as the jump is too long", offset);
+        }
+
+        let mut padded = vec![0; JUMP_WIDTH - split.len()];
+        padded.extend(split);
+        return padded;
+    }
+
     /// Removes the last emitted byte
     pub fn demit(&mut self) {
         self.code.pop();
@@ -92,80 +136,9 @@ impl Lambda {
     }
 }
 
+/// Prints the lambda as assembly, see `common::assembly::disassemble`.
 impl fmt::Display for Lambda {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "\n-- Dumping Constants:")?;
-        for constant in self.constants.iter() {
-            writeln!(f, "{:?}", constant)?;
-        }
-
-        // writeln!(f, "-- Dumping Spans:")?;
-        // for span in self.spans.iter() {
-        //     writeln!(f, "{:?}", span)?;
-        // }
-
-        writeln!(f, "-- Dumping Captures:")?;
-        for capture in self.captures.iter() {
-            writeln!(f, "{:?}", capture)?;
-        }
-
-        writeln!(f, "-- Dumping Bytecode:")?;
-        writeln!(f, "Inst.   \tArgs\tValue?")?;
-        let mut index = 0;
-
-        while index < self.code.len() {
-            index += 1;
-            match Opcode::from_byte(self.code[index - 1]) {
-                Opcode::Con => {
-                    let (constant_index, consumed) = build_number(&self.code[index..]);
-                    index += consumed;
-                    writeln!(f, "Load Con\t{}\t{:?}", constant_index, self.constants[constant_index])?;
-                },
-                Opcode::Del => { writeln!(f, "Delete  \t\t--")?; },
-                Opcode::Capture => {
-                    let (local_index, consumed) = build_number(&self.code[index..]);
-                    index += consumed;
-                    writeln!(f, "Capture \t{}\tIndexed local moved to heap", local_index)?;
-                },
-                Opcode::Save => {
-                    let (local_index, consumed) = build_number(&self.code[index..]);
-                    index += consumed;
-                    writeln!(f, "Save    \t{}\tIndexed local", local_index)?;
-                },
-                Opcode::SaveCap => {
-                    let (upvalue_index, consumed) = build_number(&self.code[index..]);
-                    index += consumed;
-                    writeln!(f, "Save Cap\t{}\tIndexed upvalue on heap", upvalue_index)?;
-                },
-                Opcode::Load => {
-                    let (local_index, consumed) = build_number(&self.code[index..]);
-                    index += consumed;
-                    writeln!(f, "Load    \t{}\tIndexed local", local_index)?;
-                },
-                Opcode::LoadCap => {
-                    let (upvalue_index, consumed) = build_number(&self.code[index..]);
-                    index += consumed;
-                    writeln!(f, "Load Cap\t{}\tIndexed upvalue on heap", upvalue_index)?;
-                },
-                Opcode::Call => { writeln!(f, "Call    \t\tRun top function using next stack value")?; }
-                Opcode::Return => {
-                    let (num_locals, consumed) = build_number(&self.code[index..]);
-                    index += consumed;
-                    writeln!(f, "Return  \t{}\tLocals on stack deleted", num_locals)?;
-                },
-                Opcode::Closure => {
-                    let (todo_index, consumed) = build_number(&self.code[index..]);
-                    index += consumed;
-                    writeln!(f, "Closure \t{}\tIndex of lambda to be wrapped", todo_index)?;
-                },
-                Opcode::Print   => { writeln!(f, "Print    \t\t--")?; },
-                Opcode::Label   => { writeln!(f, "Label    \t\t--")?; },
-                Opcode::UnLabel => { writeln!(f, "UnLabel  \t\t--")?; },
-                Opcode::UnData  => { writeln!(f, "UnData   \t\t--")?; },
-                Opcode::Copy    => { writeln!(f, "Copy     \t\t--")?; },
-            }
-        }
-
-        Ok(())
+        write!(f, "{}", disassemble(self))
     }
 }
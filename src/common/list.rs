@@ -0,0 +1,163 @@
+use std::{
+    rc::Rc,
+    iter::FromIterator,
+};
+
+use crate::common::data::Data;
+
+/// A single item in a `List`, and the rest of the list after it.
+#[derive(Debug)]
+struct Node {
+    head: Data,
+    tail: List,
+}
+
+/// A persistent singly-linked list of `Data`.
+/// Lists are immutable, and share their tails,
+/// so prepending an item to a list or splitting off its tail
+/// is cheap, and does not copy the rest of the list.
+/// An empty list is just `[]`.
+#[derive(Debug, Clone)]
+pub struct List(Option<Rc<Node>>);
+
+impl List {
+    /// Creates a new empty list.
+    pub fn empty() -> List {
+        List(None)
+    }
+
+    /// Creates a new list with an item in front of the current one.
+    pub fn prepend(&self, head: Data) -> List {
+        List(Some(Rc::new(Node { head, tail: self.clone() })))
+    }
+
+    /// Splits a list into a copy of its first item and the rest of the list.
+    /// Returns `None` if the list is empty.
+    pub fn uncons(&self) -> Option<(Data, List)> {
+        self.0.as_ref().map(|node| (node.head.clone(), node.tail.clone()))
+    }
+
+    /// Checks whether a list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_none()
+    }
+
+    /// Counts the number of items in the list.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Iterates over references to each item in the list, front to back.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter(self.0.as_deref())
+    }
+
+    /// Iterates over each item in the list,
+    /// alongside the address of the node that holds it.
+    /// Lists that share a tail share the nodes of that tail.
+    pub fn nodes(&self) -> impl Iterator<Item = (usize, &Data)> {
+        let mut node = self.0.as_deref();
+        std::iter::from_fn(move || {
+            let current = node?;
+            node = current.tail.0.as_deref();
+            Some((current as *const Node as usize, &current.head))
+        })
+    }
+
+    /// Iterates over the items at the front of the list
+    /// that are only held by this list, stopping at the first shared node.
+    pub fn owned(&self) -> impl Iterator<Item = &Data> {
+        let mut node = self.0.as_ref();
+        std::iter::from_fn(move || {
+            let current = node.filter(|n| Rc::strong_count(n) == 1)?;
+            node = current.tail.0.as_ref();
+            Some(&current.head)
+        })
+    }
+
+    /// The approximate number of bytes used by each item in a list.
+    pub const NODE_SIZE: usize = std::mem::size_of::<Node>() + 2 * std::mem::size_of::<usize>();
+}
+
+/// Iterates over the items in a `List`.
+pub struct Iter<'a>(Option<&'a Node>);
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Data;
+
+    fn next(&mut self) -> Option<&'a Data> {
+        let node = self.0?;
+        self.0 = node.tail.0.as_deref();
+        Some(&node.head)
+    }
+}
+
+impl FromIterator<Data> for List {
+    fn from_iter<I: IntoIterator<Item = Data>>(items: I) -> List {
+        let items = items.into_iter().collect::<Vec<Data>>();
+        let mut list = List::empty();
+        for item in items.into_iter().rev() {
+            list = list.prepend(item);
+        }
+        list
+    }
+}
+
+impl PartialEq for List {
+    /// Lists are equal if they have the same items in the same order.
+    /// This is checked iteratively, so long lists won't overflow the stack.
+    fn eq(&self, other: &List) -> bool {
+        let (mut a, mut b) = (self.iter(), other.iter());
+        loop {
+            // lists that share a tail are equal from that point onwards
+            if let (Some(x), Some(y)) = (a.0, b.0) {
+                if std::ptr::eq(x, y) { return true; }
+            }
+
+            match (a.next(), b.next()) {
+                (None,    None)             => return true,
+                (Some(x), Some(y)) if x == y => (),
+                _                           => return false,
+            }
+        }
+    }
+}
+
+impl Drop for List {
+    /// Drops the list iteratively rather than recursively,
+    /// so that dropping a long list won't overflow the stack.
+    fn drop(&mut self) {
+        let mut next = self.0.take();
+        while let Some(node) = next {
+            match Rc::try_unwrap(node) {
+                Ok(mut node) => next = node.tail.0.take(),
+                // the rest of the list is still in use elsewhere
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shared_tail() {
+        let tail  = vec![Data::Real(2.0), Data::Real(3.0)].into_iter().collect::<List>();
+        let whole = tail.prepend(Data::Real(1.0));
+
+        let (head, rest) = whole.uncons().unwrap();
+        assert_eq!(head, Data::Real(1.0));
+        assert_eq!(rest, tail);
+        assert_eq!(whole.len(), 3);
+    }
+
+    #[test]
+    fn long_list() {
+        // neither comparing nor dropping should overflow the stack
+        let a = (0..1_000_000).map(|n| Data::Real(n as f64)).collect::<List>();
+        let b = (0..1_000_000).map(|n| Data::Real(n as f64)).collect::<List>();
+        assert_eq!(a, b);
+    }
+}
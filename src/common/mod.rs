@@ -4,12 +4,25 @@
 //! - Core data-strucutres.
 //! - Opcodes and number splicing.
 //! - Source code representation and span annotations.
+//! - Host functions callable from Passerine.
+//! - Opaque references to fibers, which only the `vm` can run.
+//! - A versioned binary format for saving compiled bytecode,
+//!   and a verifier for checking bytecode before it's run.
+//! - A textual assembler and disassembler for bytecode,
+//!   which can annotate instructions with the source they came from.
 
 pub mod source;
 pub mod span;
 pub mod data;
+pub mod list;
 pub mod number;
 pub mod opcode;
 pub mod lambda;
+pub mod bytecode;
+pub mod verify;
+pub mod assembly;
 pub mod closure;
+pub mod ffi;
+pub mod fiber;
+pub mod convert;
 pub mod stamp;
@@ -57,6 +57,21 @@ pub fn build_number(bytes: &[u8]) -> (usize, usize) /* (index, eaten) */ {
     return (i, e);
 }
 
+/// Builds the next number in a stream of bytes, like `build_number`,
+/// but returns `None` if the stream ends before the number does,
+/// or if the number doesn't fit in a `usize`.
+pub fn try_build_number(bytes: &[u8]) -> Option<(usize, usize)> {
+    let mut i: usize = 0;
+    let chunk        = 0b1000_0000;
+
+    for (e, byte) in bytes.iter().enumerate() {
+        i = i.checked_mul(chunk)?.checked_add((byte & !(chunk as u8)) as usize)?;
+        if *byte as usize >= chunk { return Some((i, e + 1)); }
+    }
+
+    None
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -89,6 +104,16 @@ mod test {
         assert_eq!((x, eat), build_number(&extra));
     }
 
+    #[test]
+    fn checked() {
+        let x = 42069;
+        assert_eq!(try_build_number(&split_number(x)), Some((x, 3)));
+        assert_eq!(try_build_number(&split_number(x)[..2]), None);
+        let mut huge = vec![0b0111_1111; 10];
+        huge.push(0b1000_0000);
+        assert_eq!(try_build_number(&huge), None);
+    }
+
     #[test]
     fn zero() {
         let mut zero = split_number(0);
@@ -2,7 +2,7 @@
 /// Under the hood, it's just a byte.
 /// This allows non opcode bytes to be inserted in bytecode streams.
 #[repr(u8)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Opcode {
     /// Load a constant.
     Con = 0,
@@ -34,6 +34,81 @@ pub enum Opcode {
     UnLabel = 13,
     /// Destructures atomic data by asserting it matches exactly
     UnData = 14,
+    /// Reserves space on the stack for a number of uninitialized locals.
+    Reserve = 15,
+    /// Constructs a tuple out of a number of items on the stack.
+    Tuple = 16,
+    /// Destructures a tuple of a specific length onto the stack.
+    UnTuple = 17,
+    /// Constructs a record out of a tuple of field names and their values.
+    Record = 18,
+    /// Destructures a record's fields onto the stack.
+    UnRecord = 19,
+    /// Looks up the value of a field in a record.
+    Index = 20,
+    /// Constructs a list out of a number of items on the stack.
+    List = 21,
+    /// Prepends an item to the front of a list.
+    Cons = 22,
+    /// Destructures a list of a specific length onto the stack.
+    UnList = 23,
+    /// Destructures a non-empty list into its head and tail.
+    UnCons = 24,
+    /// Adds two numbers, or concatenates two strings or lists.
+    Add = 25,
+    /// Subtracts one number from another.
+    Sub = 26,
+    /// Multiplies two numbers.
+    Mul = 27,
+    /// Divides one number by another.
+    Div = 28,
+    /// Takes the remainder of dividing one number by another.
+    Rem = 29,
+    /// Checks whether two values are equal.
+    Equal = 30,
+    /// Checks whether two values are not equal.
+    NotEqual = 31,
+    /// Checks whether one number or string is less than another.
+    Less = 32,
+    /// Checks whether one number or string is less than or equal to another.
+    LessEqual = 33,
+    /// Checks whether one number or string is greater than another.
+    Greater = 34,
+    /// Checks whether one number or string is greater than or equal to another.
+    GreaterEqual = 35,
+    /// Checks whether two booleans are both true.
+    And = 36,
+    /// Checks whether either of two booleans is true.
+    Or = 37,
+    /// Negates a boolean.
+    Not = 38,
+    /// Jumps forward a number of bytes.
+    Jump = 39,
+    /// Jumps backward a number of bytes.
+    JumpBack = 40,
+    /// Pops a boolean, jumping forward a number of bytes if it's false.
+    JumpFalse = 41,
+    /// Installs a handler that jumps forward a number of bytes
+    /// if a pattern fails to match, used to try the next arm of a match.
+    Handle = 42,
+    /// Removes the most recently installed handler.
+    Unhandle = 43,
+    /// Raises an error because no arm of a match matched.
+    NoMatch = 44,
+    /// Fails to match the current pattern, used when a guard is false.
+    Fail = 45,
+    /// Wraps a closure in a new fiber.
+    Fiber = 46,
+    /// Suspends the running fiber, passing a value to the fiber that resumed it.
+    Yield = 47,
+    /// Crashes the running fiber with a value.
+    Raise = 48,
+    /// Runs a closure in a new fiber, catching any errors as a `Result`.
+    Try = 49,
+    /// Calls a function, reusing the current frame.
+    TailCall = 50,
+    /// Negates a number.
+    Neg = 51,
 }
 
 impl Opcode {
@@ -43,7 +118,49 @@ impl Opcode {
     /// This *should* never cause a crash
     /// and if it does, the vm's designed to crash hard
     /// so it'll be pretty obvious.
+    /// Bytecode that didn't come straight from the compiler
+    /// should be checked with `common::verify` first.
     pub fn from_byte(byte: u8) -> Opcode {
         unsafe { std::mem::transmute(byte) }
     }
+
+    /// Whether the opcode is followed by a split number in the bytecode.
+    pub fn has_operand(self) -> bool {
+        matches!(
+            self,
+            Opcode::Con
+            | Opcode::Capture
+            | Opcode::Save
+            | Opcode::SaveCap
+            | Opcode::Load
+            | Opcode::LoadCap
+            | Opcode::Return
+            | Opcode::Closure
+            | Opcode::Reserve
+            | Opcode::Tuple
+            | Opcode::UnTuple
+            | Opcode::List
+            | Opcode::UnList
+            | Opcode::Jump
+            | Opcode::JumpBack
+            | Opcode::JumpFalse
+            | Opcode::Handle
+        )
+    }
+
+    /// Whether the opcode's operand is a jump offset,
+    /// which is padded to `JUMP_WIDTH` bytes.
+    pub fn is_jump(self) -> bool {
+        matches!(self, Opcode::Jump | Opcode::JumpBack | Opcode::JumpFalse | Opcode::Handle)
+    }
+
+    /// Converts a raw byte to an opcode,
+    /// returning `None` if the byte isn't an opcode.
+    pub fn checked(byte: u8) -> Option<Opcode> {
+        if byte <= Opcode::Neg as u8 {
+            Some(Opcode::from_byte(byte))
+        } else {
+            None
+        }
+    }
 }
@@ -5,7 +5,6 @@ use std::{
         Debug,
         Display,
     },
-    usize,
     rc::Rc,
 };
 
@@ -45,7 +44,7 @@ impl Span {
 
     /// Checks if a `Span` is empty.
     pub fn is_empty(&self) -> bool {
-        self.source == None
+        self.source.is_none()
     }
 
     pub fn end(&self) -> usize {
@@ -83,7 +82,7 @@ impl Span {
         let length = end - offset;
 
         // `a` should not be empty at this point
-        return Span::new(&a.source.as_ref().unwrap(), offset, length);
+        return Span::new(a.source.as_ref().unwrap(), offset, length);
     }
 
     /// Combines a set of `Span`s (think fold-left over `Span::combine`).
@@ -165,7 +164,7 @@ impl Display for Span {
         }
 
         let full_source = &self.source.as_ref().unwrap().contents;
-        let lines = Span::lines(&full_source);
+        let lines = Span::lines(full_source);
 
         let (start_line, start_col) = match Span::line_index(full_source, self.offset) {
             Some(li) => li,
@@ -250,7 +249,7 @@ impl<T> Spanned<T> {
         Spanned { item, span }
     }
 
-    pub fn build(spanneds: &Vec<Spanned<T>>) -> Span {
+    pub fn build(spanneds: &[Spanned<T>]) -> Span {
         let spans = spanneds.iter()
             .map(|s| s.span.clone())
             .collect::<Vec<Span>>();
@@ -0,0 +1,743 @@
+use std::{
+    fmt,
+    collections::HashSet,
+};
+
+use crate::common::{
+    data::Data,
+    lambda::{Captured, Lambda},
+    opcode::Opcode,
+    number::try_build_number,
+};
+
+/// Why a `Lambda` was rejected by `verify`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    /// The byte is not an opcode.
+    Opcode(u8),
+    /// The number following an opcode is cut off or too large.
+    Operand,
+    /// There is no constant at the index.
+    Constant(usize),
+    /// There is no captured variable at the index.
+    Capture(usize),
+    /// There is no local at the index.
+    Local(usize),
+    /// A local captured by a closure has not been moved to the heap.
+    NotHeaped(usize),
+    /// A local is moved to the heap after it has already been moved there.
+    Heaped(usize),
+    /// A label or lambda constant is used as data,
+    /// rather than being built into a label or closure.
+    Naked,
+    /// A jump lands somewhere other than the start of an instruction.
+    Jump(usize),
+    /// The instruction pops more values than are on the stack.
+    Underflow,
+    /// The instruction can be reached with different numbers of values on the stack,
+    /// or with different handlers installed.
+    Unbalanced,
+    /// A handler is removed when none is installed.
+    Handler,
+    /// The stack grows deeper than `MAX_DEPTH`.
+    Overflow,
+    /// The value on top of the stack is not what the instruction expects.
+    Expected(&'static str),
+    /// A function returns with a number of locals other than what is on the stack.
+    Return { locals: usize, depth: usize },
+    /// A function runs past the end of its code without returning.
+    End,
+    /// The top level of a script returns or makes a tail call,
+    /// though it has no frame to return from.
+    Script,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Opcode(byte)    => write!(f, "the byte {} is not an opcode", byte),
+            Reason::Operand         => write!(f, "the operand is cut off or too large"),
+            Reason::Constant(index) => write!(f, "there is no constant at index {}", index),
+            Reason::Capture(index)  => write!(f, "there is no captured variable at index {}", index),
+            Reason::Local(index)    => write!(f, "there is no local at index {}", index),
+            Reason::NotHeaped(index) => write!(f, "the local at index {} is captured before being moved to the heap", index),
+            Reason::Heaped(index)   => write!(f, "the local at index {} is moved to the heap twice", index),
+            Reason::Naked           => write!(f, "a label or lambda constant is used as data"),
+            Reason::Jump(target)    => write!(f, "the jump to byte {} does not land on an instruction", target),
+            Reason::Underflow       => write!(f, "the stack underflows"),
+            Reason::Overflow        => write!(f, "the stack grows deeper than {} values", MAX_DEPTH),
+            Reason::Unbalanced      => write!(f, "the stack is not balanced between the paths reaching this instruction"),
+            Reason::Handler         => write!(f, "there is no handler to remove"),
+            Reason::Expected(what)  => write!(f, "expected {} on top of the stack", what),
+            Reason::Return { locals, depth } => write!(
+                f, "returns with {} locals, but there are {} values on the stack", locals, depth,
+            ),
+            Reason::End => write!(f, "the function ends without returning"),
+            Reason::Script => write!(f, "the top level of a script can not return or make a tail call"),
+        }
+    }
+}
+
+/// The most values a single function may have on the stack at once.
+pub const MAX_DEPTH: usize = 1 << 20;
+
+/// Raised when a `Lambda` is malformed, see `verify`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    /// The indices of the constants holding each nested lambda,
+    /// leading from the verified lambda to the malformed one.
+    pub path:   Vec<usize>,
+    /// The index of the offending instruction in the malformed lambda's code.
+    pub offset: usize,
+    pub reason: Reason,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Malformed lambda")?;
+        for index in self.path.iter() { write!(f, " > constant {}", index)?; }
+        write!(f, " at byte {}: {}", self.offset, self.reason)
+    }
+}
+
+/// Checks that a `Lambda`, and every `Lambda` nested in its constants,
+/// can be run by the `VM` without crashing it.
+/// The VM trusts its bytecode, so bytecode that didn't come straight from the compiler,
+/// i.e. bytecode loaded from disk, must be verified before it's run.
+///
+/// Each instruction is decoded, checking that every byte is an opcode,
+/// that every operand is a well-formed split number,
+/// and that constant, capture, and local indices are in bounds.
+/// Then every path through the code is followed,
+/// keeping track of what's on the stack,
+/// to check that the stack never underflows,
+/// that paths which meet have the same number of values on the stack,
+/// that functions return exactly the locals they declared,
+/// that instructions like `Label` and `Closure` get the data they expect,
+/// and that label and lambda constants are only used to build labels and closures.
+/// Errors raised while running, like type errors, are not checked for.
+pub fn verify(lambda: &Lambda) -> Result<(), VerifyError> {
+    Verifier::verify(lambda, false)
+}
+
+/// What is known about a value on the stack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Slot {
+    Data,
+    /// A local that has been moved to the heap.
+    Heaped,
+    /// A constant, loaded from the constant at an index.
+    Constant(usize),
+    /// A label or lambda constant, which may only be built into a label,
+    /// and must not otherwise be used as data.
+    Naked(usize),
+    Closure,
+}
+
+/// What is known about the state of the VM before an instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct State {
+    /// The values on the stack above the frame of the function.
+    stack:    Vec<Slot>,
+    /// The installed handlers, as the instruction each jumps to
+    /// and the height the stack is restored to.
+    handlers: Vec<(usize, usize)>,
+}
+
+impl State {
+    /// Combines the states of two paths that reach the same instruction,
+    /// returning whether anything changed.
+    fn merge(&mut self, other: &State) -> Result<bool, Reason> {
+        if self.stack.len() != other.stack.len() || self.handlers != other.handlers {
+            return Err(Reason::Unbalanced);
+        }
+
+        let mut changed = false;
+        for (slot, other) in self.stack.iter_mut().zip(other.stack.iter()) {
+            // a naked constant can't be forgotten about,
+            // lest it be used as data further along
+            if slot != other && (matches!(slot, Slot::Naked(_)) || matches!(other, Slot::Naked(_))) {
+                return Err(Reason::Naked);
+            }
+            if slot != other && *slot != Slot::Data {
+                *slot = Slot::Data;
+                changed = true;
+            }
+        }
+
+        Ok(changed)
+    }
+
+    /// Pops a number of values off the stack to be used as data.
+    /// Values on the heap are copied off of it when popped.
+    fn pop(&mut self, count: usize) -> Result<Vec<Slot>, Reason> {
+        if count > self.stack.len() { return Err(Reason::Underflow); }
+        self.stack.split_off(self.stack.len() - count).into_iter()
+            .map(|slot| match slot {
+                Slot::Heaped   => Ok(Slot::Data),
+                Slot::Naked(_) => Err(Reason::Naked),
+                slot           => Ok(slot),
+            })
+            .collect()
+    }
+
+    fn pop_one(&mut self) -> Result<Slot, Reason> {
+        Ok(self.pop(1)?.remove(0))
+    }
+
+    /// Pops a value off the stack that is only moved around, not used as data,
+    /// so may be a naked constant.
+    fn take(&mut self) -> Result<Slot, Reason> {
+        match self.stack.pop() {
+            Some(Slot::Heaped) => Ok(Slot::Data),
+            Some(slot) => Ok(slot),
+            None => Err(Reason::Underflow),
+        }
+    }
+
+    /// Checks that the value on top of the stack, if any, can be used as data.
+    fn peek(&self) -> Result<(), Reason> {
+        match self.stack.last() {
+            Some(Slot::Naked(_)) => Err(Reason::Naked),
+            _ => Ok(()),
+        }
+    }
+
+    /// Pushes a number of unknown values onto the stack.
+    fn push(&mut self, count: usize) -> Result<(), Reason> {
+        if count > MAX_DEPTH - self.stack.len() { return Err(Reason::Overflow); }
+        self.stack.extend((0..count).map(|_| Slot::Data));
+        Ok(())
+    }
+
+    fn push_slot(&mut self, slot: Slot) -> Result<(), Reason> {
+        self.push(1)?;
+        *self.stack.last_mut().unwrap() = slot;
+        Ok(())
+    }
+
+    /// Checks that a local is on the stack.
+    fn local(&self, index: usize) -> Result<(), Reason> {
+        if index < self.stack.len() { Ok(()) } else { Err(Reason::Local(index)) }
+    }
+}
+
+/// A single decoded instruction.
+#[derive(Debug, Clone, Copy)]
+struct Instruction {
+    opcode:  Opcode,
+    operand: usize,
+    /// The index of the next instruction.
+    next:    usize,
+}
+
+/// Follows every path through a lambda's code.
+/// States are only stored for instructions that can be jumped to;
+/// between those, the state of a path is updated in place.
+struct Verifier<'a> {
+    lambda: &'a Lambda,
+    /// Whether the lambda is a function, rather than the top level of a script.
+    nested: bool,
+    /// The decoded instruction starting at each index, if any.
+    instructions: Vec<Option<Instruction>>,
+    /// The instructions that can be jumped to.
+    targets: HashSet<usize>,
+    /// The state before each jump target, once reached.
+    states:  Vec<Option<State>>,
+    pending: Vec<usize>,
+}
+
+impl<'a> Verifier<'a> {
+    fn verify(lambda: &Lambda, nested: bool) -> Result<(), VerifyError> {
+        let error = |offset, reason| VerifyError { path: vec![], offset, reason };
+
+        let mut verifier = Verifier {
+            lambda,
+            nested,
+            instructions: vec![],
+            targets: HashSet::new(),
+            states:  vec![None; lambda.code.len()],
+            pending: vec![],
+        };
+
+        verifier.decode().map_err(|(offset, reason)| error(offset, reason))?;
+
+        // a function starts with its argument on the stack
+        let stack = if nested { vec![Slot::Data] } else { vec![] };
+        let start = State { stack, handlers: vec![] };
+        verifier.targets.insert(0);
+        verifier.reach(0, start).map_err(|reason| error(0, reason))?;
+
+        while let Some(offset) = verifier.pending.pop() {
+            let state = verifier.states[offset].clone().unwrap();
+            verifier.run(offset, state).map_err(|(offset, reason)| error(offset, reason))?;
+        }
+
+        for (index, constant) in lambda.constants.iter().enumerate() {
+            if let Data::Lambda(nested) = constant {
+                Verifier::verify(nested, true).map_err(|mut error| {
+                    error.path.insert(0, index);
+                    error
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Splits the code into instructions, collecting the targets of jumps.
+    fn decode(&mut self) -> Result<(), (usize, Reason)> {
+        let code = &self.lambda.code;
+        self.instructions = vec![None; code.len()];
+        let mut index = 0;
+
+        while index < code.len() {
+            let opcode = Opcode::checked(code[index]).ok_or((index, Reason::Opcode(code[index])))?;
+            let (operand, next) = if opcode.has_operand() {
+                try_build_number(&code[index + 1..])
+                    .map(|(operand, eaten)| (operand, index + 1 + eaten))
+                    .ok_or((index, Reason::Operand))?
+            } else {
+                (0, index + 1)
+            };
+
+            let target = match opcode {
+                Opcode::Jump | Opcode::JumpFalse | Opcode::Handle => next.checked_add(operand),
+                Opcode::JumpBack => next.checked_sub(operand),
+                _ => None,
+            };
+            if let Some(target) = target { self.targets.insert(target); }
+
+            self.instructions[index] = Some(Instruction { opcode, operand, next });
+            index = next;
+        }
+
+        Ok(())
+    }
+
+    /// Follows a path to a jump target, or to the end of the code,
+    /// merging its state with that of any other path that's reached it.
+    fn reach(&mut self, target: usize, state: State) -> Result<(), Reason> {
+        if target == self.lambda.code.len() {
+            // the end of a script leaves its value on the stack,
+            // but a function has to return
+            return if self.nested { Err(Reason::End) } else { state.peek() };
+        }
+
+        if target > self.lambda.code.len() || self.instructions[target].is_none() {
+            return Err(Reason::Jump(target));
+        }
+
+        match &mut self.states[target] {
+            Some(existing) => if existing.merge(&state)? { self.pending.push(target); },
+            empty => {
+                *empty = Some(state);
+                self.pending.push(target);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Follows a path from a jump target until it jumps, ends, or reaches another target.
+    fn run(&mut self, mut offset: usize, mut state: State) -> Result<(), (usize, Reason)> {
+        loop {
+            let instruction = self.instructions[offset].unwrap();
+            let next = match self.step(instruction, &mut state).map_err(|reason| (offset, reason))? {
+                Some(next) => next,
+                None => return Ok(()),
+            };
+
+            if next == self.lambda.code.len() || self.targets.contains(&next) {
+                return self.reach(next, state).map_err(|reason| (offset, reason));
+            }
+            offset = next;
+        }
+    }
+
+    /// Finds the constant loaded by a value on the stack.
+    fn constant(&self, slot: &Slot, expected: &'static str) -> Result<&'a Data, Reason> {
+        match slot {
+            Slot::Constant(index) | Slot::Naked(index) => Ok(&self.lambda.constants[*index]),
+            _ => Err(Reason::Expected(expected)),
+        }
+    }
+
+    /// Finds the number of fields named by a value on the stack.
+    fn field_names(&self, slot: &Slot) -> Result<usize, Reason> {
+        let expected = "a tuple of field names";
+        match self.constant(slot, expected)? {
+            Data::Tuple(names) if names.iter().all(|n| matches!(n, Data::String(_))) => Ok(names.len()),
+            _ => Err(Reason::Expected(expected)),
+        }
+    }
+
+    /// Checks that a value on the stack was loaded from a constant of the expected kind.
+    fn expect(&self, slot: &Slot, expected: &'static str, kind: fn(&Data) -> bool) -> Result<(), Reason> {
+        if kind(self.constant(slot, expected)?) { Ok(()) } else { Err(Reason::Expected(expected)) }
+    }
+
+    /// Follows the path taken when a pattern doesn't match, once the matched data is popped:
+    /// if a handler is installed, the stack is restored and the VM jumps to the handler;
+    /// otherwise an error is raised, ending the path.
+    fn mismatch(&mut self, state: &State) -> Result<(), Reason> {
+        let mut handled = state.clone();
+        let (target, height) = match handled.handlers.pop() {
+            Some(handler) => handler,
+            None => return Ok(()),
+        };
+
+        if height > handled.stack.len() { return Err(Reason::Underflow); }
+        handled.stack.truncate(height);
+        self.reach(target, handled)
+    }
+
+    /// Checks a single instruction, updating the state of the path.
+    /// Returns the index of the next instruction on the path,
+    /// or `None` if the path ends.
+    fn step(&mut self, instruction: Instruction, state: &mut State) -> Result<Option<usize>, Reason> {
+        let Instruction { opcode, operand, next } = instruction;
+        let lambda = self.lambda;
+        let capture = || if operand < lambda.captures.len() { Ok(()) } else { Err(Reason::Capture(operand)) };
+
+        match opcode {
+            Opcode::Con => {
+                let slot = match lambda.constants.get(operand) {
+                    Some(Data::Kind(_)) | Some(Data::Lambda(_)) => Slot::Naked(operand),
+                    Some(_) => Slot::Constant(operand),
+                    None    => return Err(Reason::Constant(operand)),
+                };
+                state.push_slot(slot)?;
+            },
+            Opcode::Del  => { state.take()?; },
+            Opcode::Copy => {
+                let slot = state.take()?;
+                state.push_slot(slot.clone())?;
+                state.push_slot(slot)?;
+            },
+            Opcode::Capture => {
+                state.local(operand)?;
+                match state.stack[operand] {
+                    Slot::Heaped   => return Err(Reason::Heaped(operand)),
+                    Slot::Naked(_) => return Err(Reason::Naked),
+                    _ => state.stack[operand] = Slot::Heaped,
+                }
+            },
+            Opcode::Save => {
+                // saving the value on top of the stack to its own position declares it
+                if state.stack.len().checked_sub(1) != Some(operand) {
+                    let slot = state.take()?;
+                    state.local(operand)?;
+                    // values saved to a local on the heap stay on the heap
+                    if state.stack[operand] != Slot::Heaped {
+                        state.stack[operand] = slot;
+                    } else if let Slot::Naked(_) = slot {
+                        return Err(Reason::Naked);
+                    }
+                }
+            },
+            Opcode::SaveCap => { capture()?; state.pop(1)?; },
+            Opcode::Load => {
+                state.local(operand)?;
+                let slot = match &state.stack[operand] {
+                    Slot::Heaped => Slot::Data,
+                    slot => slot.clone(),
+                };
+                state.push_slot(slot)?;
+            },
+            Opcode::LoadCap => { capture()?; state.push(1)?; },
+            Opcode::TailCall if !self.nested => return Err(Reason::Script),
+            Opcode::Return   if !self.nested => return Err(Reason::Script),
+            Opcode::Call | Opcode::TailCall => { state.pop(2)?; state.push(1)?; },
+            Opcode::Return => {
+                // the frame must hold exactly the locals and the returned value
+                if state.stack.len() != operand.saturating_add(1) {
+                    return Err(Reason::Return { locals: operand, depth: state.stack.len() });
+                }
+                state.peek()?;
+                return Ok(None);
+            },
+            Opcode::Closure => {
+                let nested = match lambda.constants.get(operand) {
+                    Some(Data::Lambda(nested)) => nested,
+                    Some(_) => return Err(Reason::Expected("a lambda constant")),
+                    None    => return Err(Reason::Constant(operand)),
+                };
+
+                for captured in nested.captures.iter() {
+                    match captured {
+                        Captured::Local(index) => match state.stack.get(*index) {
+                            Some(Slot::Heaped) => (),
+                            Some(_) => return Err(Reason::NotHeaped(*index)),
+                            None    => return Err(Reason::Local(*index)),
+                        },
+                        Captured::Nonlocal(upvalue) => if *upvalue >= lambda.captures.len() {
+                            return Err(Reason::Capture(*upvalue));
+                        },
+                    }
+                }
+                state.push_slot(Slot::Closure)?;
+            },
+            Opcode::Print => { state.pop(1)?; state.push(1)?; },
+            Opcode::Label | Opcode::UnLabel => {
+                let kind = state.take()?;
+                self.expect(&kind, "a label", |d| matches!(d, Data::Kind(_)))?;
+                state.pop(1)?;
+                if opcode == Opcode::UnLabel { self.mismatch(state)?; }
+                state.push(1)?;
+            },
+            Opcode::UnData  => { state.pop(2)?; self.mismatch(state)?; },
+            Opcode::Reserve => state.push(operand)?,
+            Opcode::Tuple | Opcode::List => { state.pop(operand)?; state.push(1)?; },
+            Opcode::UnTuple | Opcode::UnList => {
+                state.pop(1)?;
+                self.mismatch(state)?;
+                state.push(operand)?;
+            },
+            Opcode::Record => {
+                let names = state.pop_one()?;
+                let count = self.field_names(&names)?;
+                state.pop(count)?;
+                state.push(1)?;
+            },
+            Opcode::UnRecord => {
+                let names = state.pop_one()?;
+                let count = self.field_names(&names)?;
+                state.pop(1)?;
+                self.mismatch(state)?;
+                state.push(count)?;
+            },
+            Opcode::Index => {
+                let name = state.pop_one()?;
+                self.expect(&name, "a field name", |d| matches!(d, Data::String(_)))?;
+                state.pop(1)?;
+                state.push(1)?;
+            },
+            Opcode::Cons   => { state.pop(2)?; state.push(1)?; },
+            Opcode::UnCons => { state.pop(1)?; self.mismatch(state)?; state.push(2)?; },
+
+              Opcode::Add
+            | Opcode::Sub
+            | Opcode::Mul
+            | Opcode::Div
+            | Opcode::Rem
+            | Opcode::Equal
+            | Opcode::NotEqual
+            | Opcode::Less
+            | Opcode::LessEqual
+            | Opcode::Greater
+            | Opcode::GreaterEqual
+            | Opcode::And
+            | Opcode::Or => { state.pop(2)?; state.push(1)?; },
+            Opcode::Not | Opcode::Neg => { state.pop(1)?; state.push(1)?; },
+
+            Opcode::Jump => {
+                let target = next.checked_add(operand).ok_or(Reason::Operand)?;
+                self.reach(target, state.clone())?;
+                return Ok(None);
+            },
+            Opcode::JumpBack => {
+                let target = next.checked_sub(operand).ok_or(Reason::Operand)?;
+                self.reach(target, state.clone())?;
+                return Ok(None);
+            },
+            Opcode::JumpFalse => {
+                state.pop(1)?;
+                let target = next.checked_add(operand).ok_or(Reason::Operand)?;
+                self.reach(target, state.clone())?;
+            },
+            Opcode::Handle => {
+                let target = next.checked_add(operand).ok_or(Reason::Operand)?;
+                state.handlers.push((target, state.stack.len()));
+            },
+            Opcode::Unhandle => { state.handlers.pop().ok_or(Reason::Handler)?; },
+            Opcode::Fail => { self.mismatch(state)?; return Ok(None); },
+            Opcode::NoMatch | Opcode::Raise => { state.pop(1)?; return Ok(None); },
+            Opcode::Fiber | Opcode::Try => {
+                if state.pop_one()? != Slot::Closure {
+                    return Err(Reason::Expected("a closure"));
+                }
+                state.push(1)?;
+            },
+            Opcode::Yield => { state.pop(1)?; state.push(1)?; },
+        }
+
+        Ok(Some(next))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::{
+        source::Source,
+        number::split_number,
+    };
+    use crate::compiler::{lex, parse, desugar, gen};
+
+    fn compile(source: &str) -> Lambda {
+        gen(desugar(parse(lex(Source::source(source)).unwrap()).unwrap()).unwrap()).unwrap()
+    }
+
+    /// Builds a lambda out of opcodes and the operands that follow them.
+    fn assemble(ops: &[(Opcode, Option<usize>)]) -> Lambda {
+        let mut lambda = Lambda::empty();
+        for (op, operand) in ops {
+            lambda.emit(*op);
+            if let Some(n) = operand { lambda.emit_bytes(&mut split_number(*n)); }
+        }
+        lambda
+    }
+
+    fn reason(lambda: &Lambda) -> Reason {
+        verify(lambda).unwrap_err().reason
+    }
+
+    #[test]
+    fn compiled() {
+        let sources = [
+            "x = 1.0; y = x -> x + 1.0; y x",
+            "counter = n -> { step = x -> x + n; step n }; counter 2.0",
+            "fib = n -> if n < 2.0 { n } else { fib (n - 1.0) + fib (n - 2.0) }; fib 10.0",
+            "p = Point { x: 1.0, y: [2.0 & []] }; Point { x, y: [h & t] } = p; (x, h, p.x)",
+            "f = m -> match m {\n Some v | v > 1.0 -> v\n Some _ -> 0.0\n None () -> 1.0\n }\n f (Some 2.0)",
+            "((a, b), [c, d]) = ((1.0, 2.0), [3.0, 4.0]); print (a, b, c, d)",
+            "g = fiber { yield 1.0; yield 2.0 }; (g (), g ())",
+            "try { error \"oops\"; 1.0 }",
+            "loop = x -> if x == 0.0 { () } else { loop (x - 1.0) }; loop 10.0",
+            "not (true and false or \"a\" != \"b\")",
+        ];
+
+        for source in sources.iter() {
+            let lambda = compile(source);
+            assert_eq!(verify(&lambda), Ok(()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn malformed_code() {
+        let mut bad_byte = assemble(&[(Opcode::Con, Some(0))]);
+        bad_byte.constants.push(Data::Unit);
+        bad_byte.code.push(200);
+        assert_eq!(verify(&bad_byte), Err(VerifyError { path: vec![], offset: 2, reason: Reason::Opcode(200) }));
+
+        let mut cut_off = assemble(&[(Opcode::Reserve, None)]);
+        cut_off.code.push(0);
+        assert_eq!(reason(&cut_off), Reason::Operand);
+
+        assert_eq!(reason(&assemble(&[(Opcode::Con, Some(0))])), Reason::Constant(0));
+        assert_eq!(reason(&assemble(&[(Opcode::LoadCap, Some(0))])), Reason::Capture(0));
+        assert_eq!(reason(&assemble(&[(Opcode::Load, Some(0))])), Reason::Local(0));
+        assert_eq!(reason(&assemble(&[(Opcode::Del, None)])), Reason::Underflow);
+        assert_eq!(reason(&assemble(&[(Opcode::Unhandle, None)])), Reason::Handler);
+
+        // jumping into the middle of an instruction
+        assert_eq!(
+            reason(&assemble(&[(Opcode::Jump, Some(1)), (Opcode::Reserve, Some(1))])),
+            Reason::Jump(3),
+        );
+    }
+
+    #[test]
+    fn malformed_stack() {
+        // one branch leaves an extra value on the stack
+        let mut unbalanced = assemble(&[
+            (Opcode::Con, Some(0)),
+            (Opcode::JumpFalse, Some(2)),
+            (Opcode::Con, Some(0)),
+            (Opcode::Con, Some(0)),
+        ]);
+        unbalanced.constants.push(Data::Boolean(true));
+        assert_eq!(reason(&unbalanced), Reason::Unbalanced);
+
+        // a label must be built from a label constant
+        let mut label = assemble(&[(Opcode::Con, Some(0)), (Opcode::Con, Some(0)), (Opcode::Label, None)]);
+        label.constants.push(Data::Unit);
+        assert_eq!(reason(&label), Reason::Expected("a label"));
+
+        // a local must only be moved to the heap once
+        let mut twice = assemble(&[(Opcode::Reserve, Some(1)), (Opcode::Capture, Some(0)), (Opcode::Capture, Some(0))]);
+        twice.constants.push(Data::Unit);
+        assert_eq!(reason(&twice), Reason::Heaped(0));
+
+        // a local must be moved to the heap before it's captured
+        let mut nested = assemble(&[(Opcode::Return, Some(0))]);
+        nested.captures.push(Captured::Local(0));
+        let mut closure = assemble(&[(Opcode::Reserve, Some(1)), (Opcode::Closure, Some(0))]);
+        closure.constants.push(Data::Lambda(Box::new(nested)));
+        assert_eq!(reason(&closure), Reason::NotHeaped(0));
+    }
+
+    #[test]
+    fn naked_constants() {
+        let naked = |ops: &[(Opcode, Option<usize>)], constant: Data| {
+            let mut lambda = assemble(ops);
+            lambda.constants.push(constant);
+            reason(&lambda)
+        };
+        let kind = || Data::Kind("Some".to_string());
+        let lambda = || Data::Lambda(Box::new(assemble(&[(Opcode::Return, Some(0))])));
+
+        assert_eq!(naked(&[(Opcode::Con, Some(0)), (Opcode::Print, None)], kind()), Reason::Naked);
+        assert_eq!(naked(&[(Opcode::Con, Some(0)), (Opcode::Print, None)], lambda()), Reason::Naked);
+        assert_eq!(naked(&[(Opcode::Con, Some(0)), (Opcode::Con, Some(0)), (Opcode::Add, None)], kind()), Reason::Naked);
+        assert_eq!(naked(&[(Opcode::Con, Some(0)), (Opcode::Con, Some(0)), (Opcode::Label, None)], kind()), Reason::Naked);
+        assert_eq!(naked(&[(Opcode::Con, Some(0)), (Opcode::Capture, Some(0))], kind()), Reason::Naked);
+        // the script can't return a naked constant either
+        assert_eq!(naked(&[(Opcode::Con, Some(0)), (Opcode::Copy, None), (Opcode::Del, None)], kind()), Reason::Naked);
+
+        // a naked constant can't be merged with data
+        let mut merged = assemble(&[
+            (Opcode::Con, Some(0)),
+            (Opcode::JumpFalse, Some(4)),
+            (Opcode::Con, Some(1)),
+            (Opcode::Jump, Some(2)),
+            (Opcode::Con, Some(0)),
+            (Opcode::Print, None),
+        ]);
+        merged.constants.push(Data::Boolean(true));
+        merged.constants.push(kind());
+        assert_eq!(reason(&merged), Reason::Naked);
+
+        // moving it around before building a label is fine
+        let mut label = assemble(&[
+            (Opcode::Reserve, Some(1)),
+            (Opcode::Con, Some(0)),
+            (Opcode::Con, Some(1)),
+            (Opcode::Save, Some(0)),
+            (Opcode::Load, Some(0)),
+            (Opcode::Copy, None),
+            (Opcode::Del, None),
+            (Opcode::Label, None),
+        ]);
+        label.constants.push(Data::Unit);
+        label.constants.push(kind());
+        assert_eq!(verify(&label), Ok(()));
+    }
+
+    #[test]
+    fn malformed_nested() {
+        let mut lambda = compile("a = x -> x; b = y -> { z = y; z }; (a, b)");
+        let index = lambda.constants.iter().rposition(|c| matches!(c, Data::Lambda(_))).unwrap();
+
+        fn nested(lambda: &mut Lambda, index: usize) -> &mut Lambda {
+            match &mut lambda.constants[index] {
+                Data::Lambda(nested) => nested,
+                _ => unreachable!(),
+            }
+        }
+
+        // the nested function forgets one of its locals when it returns
+        let last = nested(&mut lambda, index).code.len() - 1;
+        nested(&mut lambda, index).code[last] -= 1;
+
+        let error = verify(&lambda).unwrap_err();
+        assert_eq!(error.path, vec![index]);
+        assert_eq!(error.offset, last - 1);
+        assert_eq!(error.reason, Reason::Return { locals: 1, depth: 3 });
+
+        // a function has to return
+        nested(&mut lambda, index).code.truncate(last - 1);
+        assert_eq!(verify(&lambda).unwrap_err().reason, Reason::End);
+    }
+}
@@ -5,12 +5,26 @@ use crate::common::{
     data::Data,
 };
 
+use crate::compiler::operator::BinOp;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ASTPattern {
     Symbol(String),
     Data(Data),
+    Discard,
     Chain(Vec<Spanned<ASTPattern>>),
     Label(String, Box<Spanned<ASTPattern>>),
+    Tuple(Vec<Spanned<ASTPattern>>),
+    Record(Vec<(String, Spanned<ASTPattern>)>),
+    List(Vec<Spanned<ASTPattern>>),
+    Cons {
+        head: Box<Spanned<ASTPattern>>,
+        tail: Box<Spanned<ASTPattern>>,
+    },
+    Where {
+        pattern: Box<Spanned<ASTPattern>>,
+        guard:   Box<Spanned<AST>>,
+    },
 }
 
 impl ASTPattern {
@@ -18,6 +32,11 @@ impl ASTPattern {
     pub fn label(name: String, pattern: Spanned<ASTPattern>) -> ASTPattern {
         ASTPattern::Label(name, Box::new(pattern))
     }
+
+    /// Shortcut for creating a `Pattern::Where` variant.
+    pub fn where_(pattern: Spanned<ASTPattern>, guard: Spanned<AST>) -> ASTPattern {
+        ASTPattern::Where { pattern: Box::new(pattern), guard: Box::new(guard) }
+    }
 }
 
 impl TryFrom<AST> for ASTPattern {
@@ -26,10 +45,50 @@ impl TryFrom<AST> for ASTPattern {
     fn try_from(ast: AST) -> Result<Self, Self::Error> {
         Ok(
             match ast {
+                AST::Symbol(s) if s == "_" => ASTPattern::Discard,
                 AST::Symbol(s) => ASTPattern::Symbol(s),
                 AST::Data(d) => ASTPattern::Data(d),
                 AST::Label(k, a) => ASTPattern::Label(k, Box::new(a.map(ASTPattern::try_from)?)),
                 AST::Pattern(p) => p,
+                AST::Tuple(t) => {
+                    let mut patterns = vec![];
+                    for item in t {
+                        patterns.push(item.map(ASTPattern::try_from)?);
+                    }
+                    ASTPattern::Tuple(patterns)
+                },
+                AST::Record(r) => {
+                    let mut fields = vec![];
+                    for (field, item) in r {
+                        fields.push((field, item.map(ASTPattern::try_from)?));
+                    }
+                    ASTPattern::Record(fields)
+                },
+                AST::List(l) => {
+                    let mut patterns = vec![];
+                    for item in l {
+                        patterns.push(item.map(ASTPattern::try_from)?);
+                    }
+                    ASTPattern::List(patterns)
+                },
+                AST::Cons { head, tail } => ASTPattern::Cons {
+                    head: Box::new(head.map(ASTPattern::try_from)?),
+                    tail: Box::new(tail.map(ASTPattern::try_from)?),
+                },
+                // blocks can't be patterns, so a block of symbols,
+                // like `{ name }`, is a record with shorthand fields
+                AST::Block(b) if !b.is_empty() => {
+                    let mut fields = vec![];
+                    for item in b {
+                        match item.item {
+                            AST::Symbol(s) => fields.push(
+                                (s.clone(), Spanned::new(ASTPattern::Symbol(s), item.span))
+                            ),
+                            _ => Err("Unexpected block inside pattern")?,
+                        }
+                    }
+                    ASTPattern::Record(fields)
+                },
                 AST::Form(f) => {
                     let mut patterns = vec![];
                     for item in f {
@@ -99,7 +158,38 @@ pub enum AST {
         function: Box<Spanned<AST>>,
     },
     Print(Box<Spanned<AST>>),
+    Fiber(Box<Spanned<AST>>),
+    Yield(Box<Spanned<AST>>),
+    Error(Box<Spanned<AST>>),
+    Try(Box<Spanned<AST>>),
     Label(String, Box<Spanned<AST>>),
+    Tuple(Vec<Spanned<AST>>),
+    Record(Vec<(String, Spanned<AST>)>),
+    List(Vec<Spanned<AST>>),
+    Cons {
+        head: Box<Spanned<AST>>,
+        tail: Box<Spanned<AST>>,
+    },
+    BinOp {
+        op:    BinOp,
+        left:  Box<Spanned<AST>>,
+        right: Box<Spanned<AST>>,
+    },
+    Not(Box<Spanned<AST>>),
+    Neg(Box<Spanned<AST>>),
+    If {
+        condition: Box<Spanned<AST>>,
+        then:      Box<Spanned<AST>>,
+        otherwise: Box<Spanned<AST>>,
+    },
+    Match {
+        value: Box<Spanned<AST>>,
+        arms:  Vec<(Spanned<ASTPattern>, Spanned<AST>)>,
+    },
+    Index {
+        expression: Box<Spanned<AST>>,
+        field:      String,
+    },
     Syntax {
         arg_pat:    Box<Spanned<ArgPat>>,
         expression: Box<Spanned<AST>>,
@@ -139,6 +229,62 @@ impl AST {
         }
     }
 
+    /// Shortcut for creating an `AST::Cons` variant.
+    pub fn cons(
+        head: Spanned<AST>,
+        tail: Spanned<AST>,
+    ) -> AST {
+        AST::Cons {
+            head: Box::new(head),
+            tail: Box::new(tail),
+        }
+    }
+
+    /// Shortcut for creating an `AST::BinOp` variant.
+    pub fn binop(
+        op:    BinOp,
+        left:  Spanned<AST>,
+        right: Spanned<AST>,
+    ) -> AST {
+        AST::BinOp {
+            op,
+            left:  Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    /// Shortcut for creating an `AST::If` variant.
+    pub fn if_else(
+        condition: Spanned<AST>,
+        then:      Spanned<AST>,
+        otherwise: Spanned<AST>,
+    ) -> AST {
+        AST::If {
+            condition: Box::new(condition),
+            then:      Box::new(then),
+            otherwise: Box::new(otherwise),
+        }
+    }
+
+    /// Shortcut for creating an `AST::Match` variant.
+    pub fn match_(
+        value: Spanned<AST>,
+        arms:  Vec<(Spanned<ASTPattern>, Spanned<AST>)>,
+    ) -> AST {
+        AST::Match { value: Box::new(value), arms }
+    }
+
+    /// Shortcut for creating an `AST::Index` variant.
+    pub fn index(
+        expression: Spanned<AST>,
+        field: String,
+    ) -> AST {
+        AST::Index {
+            expression: Box::new(expression),
+            field,
+        }
+    }
+
     /// Shortcut for creating an `AST::Syntax` variant.
     /// i.e. a macro definition
     pub fn syntax(
@@ -1,35 +1,30 @@
-use std::convert::TryFrom;
-
 use crate::common::{
     span::Spanned,
     data::Data,
 };
 
-use crate::compiler::ast::ASTPattern;
-
-// TODO: create a pattern specific to the CST?
-// Once where (i.e. `x | x > 0`) is added?
+use crate::compiler::operator::BinOp;
 
+/// A pattern in the `CST`.
+/// Unlike an `ASTPattern`, the guards of a `CSTPattern` are desugared,
+/// so patterns are converted by the `Transformer` in `desugar.rs`.
 #[derive(Debug, Clone, PartialEq)]
 pub enum CSTPattern {
     Symbol(String),
     Data(Data),
+    Discard,
     Label(String, Box<Spanned<CSTPattern>>),
-}
-
-impl TryFrom<ASTPattern> for CSTPattern {
-    type Error = String;
-
-    fn try_from(ast_pattern: ASTPattern) -> Result<Self, Self::Error> {
-        Ok(
-            match ast_pattern {
-                ASTPattern::Symbol(s)   => CSTPattern::Symbol(s),
-                ASTPattern::Data(d)     => CSTPattern::Data(d),
-                ASTPattern::Label(k, a) => CSTPattern::Label(k, Box::new(a.map(CSTPattern::try_from)?)),
-                ASTPattern::Chain(_)    => Err("Unexpected chained construct inside pattern")?,
-            }
-        )
-    }
+    Tuple(Vec<Spanned<CSTPattern>>),
+    Record(Vec<(String, Spanned<CSTPattern>)>),
+    List(Vec<Spanned<CSTPattern>>),
+    Cons {
+        head: Box<Spanned<CSTPattern>>,
+        tail: Box<Spanned<CSTPattern>>,
+    },
+    Where {
+        pattern: Box<Spanned<CSTPattern>>,
+        guard:   Box<Spanned<CST>>,
+    },
 }
 
 // NOTE: there are a lot of similar items (i.e. binops, (p & e), etc.)
@@ -57,7 +52,40 @@ pub enum CST {
         arg: Box<Spanned<CST>>,
     },
     Print(Box<Spanned<CST>>),
+    /// Wraps a lambda, which is called when the fiber is first resumed.
+    Fiber(Box<Spanned<CST>>),
+    Yield(Box<Spanned<CST>>),
+    Error(Box<Spanned<CST>>),
+    /// Wraps a lambda, which is called in a new fiber.
+    Try(Box<Spanned<CST>>),
     Label(String, Box<Spanned<CST>>),
+    Tuple(Vec<Spanned<CST>>),
+    Record(Vec<(String, Spanned<CST>)>),
+    List(Vec<Spanned<CST>>),
+    Cons {
+        head: Box<Spanned<CST>>,
+        tail: Box<Spanned<CST>>,
+    },
+    BinOp {
+        op:    BinOp,
+        left:  Box<Spanned<CST>>,
+        right: Box<Spanned<CST>>,
+    },
+    Not(Box<Spanned<CST>>),
+    Neg(Box<Spanned<CST>>),
+    If {
+        condition: Box<Spanned<CST>>,
+        then:      Box<Spanned<CST>>,
+        otherwise: Box<Spanned<CST>>,
+    },
+    Match {
+        value: Box<Spanned<CST>>,
+        arms:  Vec<(Spanned<CSTPattern>, Spanned<CST>)>,
+    },
+    Index {
+        expression: Box<Spanned<CST>>,
+        field:      String,
+    },
     // TODO: support following constructs as they are implemented
     // Macro {
     //     pattern:    Box<CST>,
@@ -1,7 +1,4 @@
-use std::{
-    convert::TryFrom,
-    collections::HashSet,
-};
+use std::collections::HashSet;
 
 use crate::common::span::{Span, Spanned};
 
@@ -46,7 +43,35 @@ impl Transformer {
             AST::Assign { pattern, expression } => self.assign(*pattern, *expression)?,
             AST::Lambda { pattern, expression } => self.lambda(*pattern, *expression)?,
             AST::Print(e) => CST::Print(Box::new(self.walk(*e)?)),
+            AST::Fiber(e) => CST::Fiber(Box::new(self.thunk(*e)?)),
+            AST::Yield(e) => CST::Yield(Box::new(self.walk(*e)?)),
+            AST::Error(e) => CST::Error(Box::new(self.walk(*e)?)),
+            AST::Try(e)   => CST::Try(Box::new(self.thunk(*e)?)),
             AST::Label(n, e) => CST::Label(n, Box::new(self.walk(*e)?)),
+            AST::Tuple(t) => self.tuple(t)?,
+            AST::Record(r) => self.record(r)?,
+            AST::List(l) => self.list(l)?,
+            AST::Cons { head, tail } => CST::Cons {
+                head: Box::new(self.walk(*head)?),
+                tail: Box::new(self.walk(*tail)?),
+            },
+            AST::BinOp { op, left, right } => CST::BinOp {
+                op,
+                left:  Box::new(self.walk(*left)?),
+                right: Box::new(self.walk(*right)?),
+            },
+            AST::Not(e) => CST::Not(Box::new(self.walk(*e)?)),
+            AST::Neg(e) => CST::Neg(Box::new(self.walk(*e)?)),
+            AST::If { condition, then, otherwise } => CST::If {
+                condition: Box::new(self.walk(*condition)?),
+                then:      Box::new(self.walk(*then)?),
+                otherwise: Box::new(self.walk(*otherwise)?),
+            },
+            AST::Match { value, arms } => self.match_(*value, arms)?,
+            AST::Index { expression, field } => CST::Index {
+                expression: Box::new(self.walk(*expression)?),
+                field,
+            },
         };
 
         return Ok(Spanned::new(cst, ast.span))
@@ -113,7 +138,7 @@ impl Transformer {
             }
         }
 
-        if matches.len() == 0 { return self.call(form); }
+        if matches.is_empty() { return self.call(form); }
         if matches.len() > 1 {
             // TODO: make the error prettier
             // might have to rework Syntax a bit...
@@ -155,26 +180,94 @@ impl Transformer {
         Ok(CST::Block(expressions))
     }
 
+    pub fn tuple(&mut self, t: Vec<Spanned<AST>>) -> Result<CST, Syntax> {
+        let mut items = vec![];
+        for i in t {
+            items.push(self.walk(i)?)
+        }
+
+        Ok(CST::Tuple(items))
+    }
+
+    pub fn list(&mut self, l: Vec<Spanned<AST>>) -> Result<CST, Syntax> {
+        let mut items = vec![];
+        for i in l {
+            items.push(self.walk(i)?)
+        }
+
+        Ok(CST::List(items))
+    }
+
+    pub fn record(&mut self, r: Vec<(String, Spanned<AST>)>) -> Result<CST, Syntax> {
+        let mut fields = vec![];
+        for (field, value) in r {
+            fields.push((field, self.walk(value)?))
+        }
+
+        Ok(CST::Record(fields))
+    }
+
+    /// Desugars an `ASTPattern` into a `CSTPattern`.
+    /// Patterns are mostly translated one to one,
+    /// but the guards of where patterns are expressions that must be desugared.
+    pub fn pattern(&mut self, p: Spanned<ASTPattern>) -> Result<Spanned<CSTPattern>, Syntax> {
+        let pattern = match p.item {
+            ASTPattern::Symbol(s)   => CSTPattern::Symbol(s),
+            ASTPattern::Data(d)     => CSTPattern::Data(d),
+            ASTPattern::Discard     => CSTPattern::Discard,
+            ASTPattern::Label(k, a) => CSTPattern::Label(k, Box::new(self.pattern(*a)?)),
+            ASTPattern::Tuple(t)    => {
+                let mut patterns = vec![];
+                for item in t {
+                    patterns.push(self.pattern(item)?);
+                }
+                CSTPattern::Tuple(patterns)
+            },
+            ASTPattern::Record(r)   => {
+                let mut fields = vec![];
+                for (field, item) in r {
+                    fields.push((field, self.pattern(item)?));
+                }
+                CSTPattern::Record(fields)
+            },
+            ASTPattern::List(l)     => {
+                let mut patterns = vec![];
+                for item in l {
+                    patterns.push(self.pattern(item)?);
+                }
+                CSTPattern::List(patterns)
+            },
+            ASTPattern::Cons { head, tail } => CSTPattern::Cons {
+                head: Box::new(self.pattern(*head)?),
+                tail: Box::new(self.pattern(*tail)?),
+            },
+            ASTPattern::Where { pattern, guard } => CSTPattern::Where {
+                pattern: Box::new(self.pattern(*pattern)?),
+                guard:   Box::new(self.walk(*guard)?),
+            },
+            ASTPattern::Chain(_) => return Err(Syntax::error(
+                "Unexpected chained construct inside pattern", &p.span,
+            )),
+        };
+
+        return Ok(Spanned::new(pattern, p.span));
+    }
+
     /// TODO: implement full pattern matching
     pub fn assign(&mut self, p: Spanned<ASTPattern>, e: Spanned<AST>) -> Result<CST, Syntax> {
-        let p_span = p.span.clone();
-
         Ok(CST::assign(
-            p.map(CSTPattern::try_from)
-                .map_err(|err| Syntax::error(&err, &p_span))?,
+            self.pattern(p)?,
             self.walk(e)?
         ))
     }
 
     /// TODO: implement full pattern matching
     pub fn lambda(&mut self, p: Spanned<ASTPattern>, e: Spanned<AST>) -> Result<CST, Syntax> {
-        let p_span = p.span.clone();
         let arguments = if let ASTPattern::Chain(c) = p.item { c } else { vec![p] };
         let mut expression = self.walk(e)?;
 
         for argument in arguments.into_iter().rev() {
-            let pattern = argument.map(CSTPattern::try_from)
-                .map_err(|err| Syntax::error(&err, &p_span))?;
+            let pattern = self.pattern(argument)?;
 
             let combined = Span::combine(&pattern.span, &expression.span);
             expression   = Spanned::new(CST::lambda(pattern, expression), combined);
@@ -183,6 +276,28 @@ impl Transformer {
         return Ok(expression.item);
     }
 
+    /// Wraps an expression in a lambda that discards its argument,
+    /// so it can be run in a new fiber, as the body of a `fiber` or `try`.
+    pub fn thunk(&mut self, e: Spanned<AST>) -> Result<Spanned<CST>, Syntax> {
+        let span = e.span.clone();
+        let body = self.walk(e)?;
+        let discard = Spanned::new(CSTPattern::Discard, Span::empty());
+        return Ok(Spanned::new(CST::lambda(discard, body), span));
+    }
+
+    pub fn match_(
+        &mut self,
+        value: Spanned<AST>,
+        arms:  Vec<(Spanned<ASTPattern>, Spanned<AST>)>,
+    ) -> Result<CST, Syntax> {
+        let mut desugared = vec![];
+        for (p, e) in arms {
+            desugared.push((self.pattern(p)?, self.walk(e)?));
+        }
+
+        Ok(CST::Match { value: Box::new(self.walk(value)?), arms: desugared })
+    }
+
     pub fn rule(&mut self, arg_pat: Spanned<ArgPat>, tree: Spanned<AST>) -> Result<CST, Syntax> {
         let patterns_span = arg_pat.span.clone();
         let rule = Rule::new(arg_pat, tree)?;
@@ -6,19 +6,36 @@ use crate::common::{
     lambda::{Captured, Lambda},
     opcode::Opcode,
     data::Data,
+    ffi::{FFI, FFIFunction},
 };
 
 use crate::compiler::{
     cst::{CST, CSTPattern},
     // TODO: CST specific pattern once where?
+    operator::BinOp,
     syntax::Syntax,
 };
 
 /// Simple function that generates unoptimized bytecode from an `CST`.
 /// Exposes the functionality of the `Compiler`.
 pub fn gen(cst: Spanned<CST>) -> Result<Lambda, Syntax> {
+    gen_with_ffi(cst, FFI::new())
+}
+
+/// Generates bytecode for a CST,
+/// resolving variables that aren't defined in the CST to host functions in the `FFI`.
+pub fn gen_with_ffi(cst: Spanned<CST>, ffi: FFI) -> Result<Lambda, Syntax> {
     let mut compiler = Compiler::base();
+    compiler.ffi = ffi;
+    let mut declarations = vec![];
+    Compiler::declarations(&cst.item, &mut declarations);
+    let hoisted = compiler.hoist(declarations);
+    compiler.reserve(hoisted);
     compiler.walk(&cst)?;
+
+    // every undefined variable is reported at once
+    if let Some(undefined) = Syntax::all(compiler.undefined) { return Err(undefined); }
+
     return Ok(compiler.lambda);
 }
 
@@ -37,6 +54,34 @@ impl Local {
     }
 }
 
+/// The number of single character insertions, deletions, substitutions,
+/// or swaps of adjacent characters needed to turn one string into another,
+/// editing each part of the string at most once (i.e. the optimal string alignment distance).
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    // the distances between the starts of `a` read by the last two rows and each start of `b`
+    let mut before = vec![0; b.len() + 1];
+    let mut last   = (0..=b.len()).collect::<Vec<_>>();
+
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+
+        for j in 1..=b.len() {
+            let substituted = last[j - 1] + if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            row[j] = substituted.min(row[j - 1] + 1).min(last[j] + 1);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+
+        before = mem::replace(&mut last, row);
+    }
+
+    last[b.len()]
+}
+
 /// Compiler is a bytecode generator that walks an CST and produces (unoptimized) Bytecode.
 /// There are plans to add a bytecode optimizer in the future.
 /// Note that this struct should not be controlled manually,
@@ -52,9 +97,21 @@ pub struct Compiler {
     captures: Vec<usize>,
     /// The nested depth of the current compiler.
     depth: usize,
+    /// Host functions, only set on the base compiler.
+    ffi: FFI,
+    /// Errors for variables that could not be resolved,
+    /// only collected on the base compiler.
+    undefined: Vec<Syntax>,
 }
 
 impl Compiler {
+    /// The name of a local reserved for a binding made by a match arm.
+    /// Hoisting declares one for each such binding in a scope,
+    /// and each is given the binding's name while its arm is compiled.
+    const ARM: &'static str = "#arm";
+    /// The name of a local whose match arm has been compiled.
+    const CLOSED: &'static str = "#closed";
+
     /// Construct a new `Compiler`.
     pub fn base() -> Compiler {
         Compiler {
@@ -63,6 +120,8 @@ impl Compiler {
             locals:    vec![],
             captures:  vec![],
             depth:     0,
+            ffi:       FFI::new(),
+            undefined: vec![],
         }
     }
 
@@ -73,6 +132,128 @@ impl Compiler {
         )
     }
 
+    /// Collects the names of all variables assigned to in a `CST`,
+    /// in the order they're first assigned to.
+    /// Lambdas introduce a new scope, so their bodies are not searched.
+    pub fn declarations(cst: &CST, names: &mut Vec<String>) {
+        match cst {
+            CST::Assign { pattern, expression } => {
+                Compiler::bindings(&pattern.item, names);
+                Compiler::declarations(&expression.item, names);
+            },
+            CST::Block(children) | CST::Tuple(children) | CST::List(children) => {
+                for child in children { Compiler::declarations(&child.item, names); }
+            },
+            CST::Call { fun, arg } => {
+                Compiler::declarations(&fun.item, names);
+                Compiler::declarations(&arg.item, names);
+            },
+            CST::Cons { head, tail } => {
+                Compiler::declarations(&head.item, names);
+                Compiler::declarations(&tail.item, names);
+            },
+            CST::If { condition, then, otherwise } => {
+                Compiler::declarations(&condition.item, names);
+                Compiler::declarations(&then.item, names);
+                Compiler::declarations(&otherwise.item, names);
+            },
+            CST::Match { value, arms } => {
+                Compiler::declarations(&value.item, names);
+                for (pattern, expression) in arms {
+                    // each arm binds its pattern to locals of its own, see `match_`
+                    let mut bound = vec![];
+                    Compiler::bindings(&pattern.item, &mut bound);
+                    names.extend(bound.iter().map(|_| Compiler::ARM.to_string()));
+
+                    let mut assigned = vec![];
+                    Compiler::declarations(&expression.item, &mut assigned);
+                    for name in assigned {
+                        if name == Compiler::ARM || !(bound.contains(&name) || names.contains(&name)) {
+                            names.push(name);
+                        }
+                    }
+                }
+            },
+            CST::BinOp { left, right, .. } => {
+                Compiler::declarations(&left.item, names);
+                Compiler::declarations(&right.item, names);
+            },
+            CST::Record(fields) => {
+                for (_, value) in fields { Compiler::declarations(&value.item, names); }
+            },
+            CST::Print(expression)
+            | CST::Fiber(expression)
+            | CST::Yield(expression)
+            | CST::Error(expression)
+            | CST::Try(expression)
+            | CST::Not(expression)
+            | CST::Neg(expression)
+            | CST::Label(_, expression)
+            | CST::Index { expression, .. } => {
+                Compiler::declarations(&expression.item, names);
+            },
+            CST::Symbol(_) | CST::Data(_) | CST::Lambda { .. } => (),
+        }
+    }
+
+    /// Collects the names of all variables bound by a pattern.
+    pub fn bindings(pattern: &CSTPattern, names: &mut Vec<String>) {
+        match pattern {
+            CSTPattern::Symbol(name) => if !names.contains(name) {
+                names.push(name.clone());
+            },
+            CSTPattern::Data(_) | CSTPattern::Discard => (),
+            CSTPattern::Where { pattern, guard } => {
+                Compiler::bindings(&pattern.item, names);
+                Compiler::declarations(&guard.item, names);
+            },
+            CSTPattern::Label(_, pattern) => Compiler::bindings(&pattern.item, names),
+            CSTPattern::Tuple(patterns) | CSTPattern::List(patterns) => {
+                for pattern in patterns { Compiler::bindings(&pattern.item, names); }
+            },
+            CSTPattern::Cons { head, tail } => {
+                Compiler::bindings(&head.item, names);
+                Compiler::bindings(&tail.item, names);
+            },
+            CSTPattern::Record(fields) => {
+                for (_, pattern) in fields { Compiler::bindings(&pattern.item, names); }
+            },
+        }
+    }
+
+    /// Returns whether a variable has been declared in the current scope,
+    /// or any enclosing one.
+    /// Unlike `captured`, this does not capture the variable.
+    pub fn resolvable(&self, name: &str) -> bool {
+        self.local(name).is_some()
+            || self.enclosing.as_ref().is_some_and(|e| e.resolvable(name))
+    }
+
+    /// Hoists variables so they can be declared at the start of a scope.
+    /// Each name that can not be resolved is declared as a local,
+    /// the rest are assigned to as usual.
+    /// Locals for match arms are always declared.
+    /// Returns the number of locals that were declared.
+    pub fn hoist(&mut self, names: Vec<String>) -> usize {
+        let mut declared = 0;
+        for name in names {
+            if name == Compiler::ARM || !self.resolvable(&name) {
+                self.declare(name);
+                declared += 1;
+            }
+        }
+        return declared;
+    }
+
+    /// Reserves space on the stack for newly declared locals.
+    /// Locals must be reserved before any temporaries are pushed,
+    /// so that each local's index is also its position in the stack frame.
+    pub fn reserve(&mut self, count: usize) {
+        if count == 0 { return; }
+        self.lambda.emit(Opcode::Reserve);
+        self.lambda.emit_bytes(&mut split_number(count));
+    }
+
     /// Replace the current compiler with a fresh one,
     /// keeping a reference to the old one in `self.enclosing`.
     pub fn enter_scope(&mut self) {
@@ -86,7 +267,7 @@ impl Compiler {
     /// Restore the enclosing compiler,
     /// returning the nested one for data extraction.
     pub fn exit_scope(&mut self) -> Compiler {
-        let enclosing = mem::replace(&mut self.enclosing, None);
+        let enclosing = self.enclosing.take();
         let nested = match enclosing {
             Some(compiler) => mem::replace(self, *compiler),
             None => panic!("Can not go back past base compiler"),
@@ -104,17 +285,52 @@ impl Compiler {
 
         // push left, push right, push center
         return match cst.item.clone() {
-            CST::Data(data) => Ok(self.data(data)),
+            CST::Data(data) => { self.data(data); Ok(()) },
             CST::Symbol(name) => self.symbol(&name, cst.span.clone()),
-            CST::Block(block) => self.block(block),
-            CST::Print(expression) => self.print(*expression),
+            CST::Block(block) => self.block(block, false),
+            CST::Print(expression) => self.print(*expression, cst.span.clone()),
+            CST::Fiber(lambda) => self.fiber(*lambda),
+            CST::Yield(expression) => self.yield_(*expression, cst.span.clone()),
+            CST::Error(expression) => self.error(*expression, cst.span.clone()),
+            CST::Try(lambda) => self.try_(*lambda),
             CST::Label(name, expression) => self.label(name, *expression),
+            CST::Tuple(items) => self.tuple(items),
+            CST::Record(fields) => self.record(fields),
+            CST::List(items) => self.list(items),
+            CST::Cons { head, tail } => self.cons(*head, *tail, cst.span.clone()),
+            CST::BinOp { op, left, right } => self.binop(op, *left, *right, cst.span.clone()),
+            CST::Not(expression) => self.not(*expression, cst.span.clone()),
+            CST::Neg(expression) => self.neg(*expression, cst.span.clone()),
+            CST::If { condition, then, otherwise } => self.if_else(*condition, *then, *otherwise, false),
+            CST::Match { value, arms } => self.match_(*value, arms, false),
+            CST::Index { expression, field } => self.index(*expression, field, cst.span.clone()),
             CST::Assign { pattern, expression } => self.assign(*pattern, *expression),
             CST::Lambda { pattern, expression } => self.lambda(*pattern, *expression),
             CST::Call   { fun,     arg        } => self.call(*fun, *arg),
         };
     }
 
+    /// Walks a CST in tail position,
+    /// i.e. as the last thing a function does before returning.
+    /// Calls in tail position reuse the frame of the current function,
+    /// so recursive loops run in constant space.
+    pub fn walk_tail(&mut self, cst: &Spanned<CST>) -> Result<(), Syntax> {
+        self.lambda.emit_span(&cst.span);
+
+        return match cst.item.clone() {
+            CST::Block(block) => self.block(block, true),
+            CST::If { condition, then, otherwise } => self.if_else(*condition, *then, *otherwise, true),
+            CST::Match { value, arms } => self.match_(*value, arms, true),
+            CST::Call { fun, arg } => self.tail_call(*fun, *arg),
+            _ => self.walk(cst),
+        };
+    }
+
+    /// Walks a CST, in tail position if `tail` is set.
+    fn walk_in(&mut self, cst: &Spanned<CST>, tail: bool) -> Result<(), Syntax> {
+        if tail { self.walk_tail(cst) } else { self.walk(cst) }
+    }
+
     /// Takes a `Data` leaf and and produces some code to load the constant
     pub fn data(&mut self, data: Data) {
         self.lambda.emit(Opcode::Con);
@@ -125,8 +341,9 @@ impl Compiler {
     // TODO: nested too deep :(
     /// Returns the relative position on the stack of a declared local,
     /// if it exists in the current scope.
+    /// Later locals shadow earlier ones, so a match arm's bindings take precedence.
     pub fn local(&self, name: &str) -> Option<usize> {
-        for (index, l) in self.locals.iter().enumerate() {
+        for (index, l) in self.locals.iter().enumerate().rev() {
             if name == l.name {
                 return Some(index)
             }
@@ -135,6 +352,28 @@ impl Compiler {
         return None;
     }
 
+    /// Gives the names bound by a match arm to locals reserved when hoisting,
+    /// returning the indices of those locals.
+    pub fn open_arm(&mut self, names: Vec<String>) -> Vec<usize> {
+        let mut opened = vec![];
+        for name in names.into_iter().filter(|n| n != Compiler::ARM) {
+            let index = self.locals.iter()
+                .position(|l| l.name == Compiler::ARM)
+                .expect("Locals for match arms should have been hoisted");
+            self.locals[index].name = name;
+            opened.push(index);
+        }
+        return opened;
+    }
+
+    /// Hides the locals bound by a match arm once the arm has been compiled.
+    /// They're never reused, as a closure made in the arm may have captured them.
+    pub fn close_arm(&mut self, opened: Vec<usize>) {
+        for index in opened {
+            self.locals[index].name = Compiler::CLOSED.to_string();
+        }
+    }
+
 
     /// Tries to resolve a variable in enclosing scopes
     /// if resolution it successful, it captures the variable in the original scope
@@ -151,13 +390,15 @@ impl Compiler {
         }
 
         if let Some(enclosing) = self.enclosing.as_mut() {
-            if let Some((captured, already)) = enclosing.captured(name) {
-                let upvalue = if !already {
+            if let Some((captured, _)) = enclosing.captured(name) {
+                // sibling lambdas may capture the same variable,
+                // so check whether *this* lambda has captured it already.
+                let position = self.lambda.captures.iter().position(|c| c == &captured);
+                let already = position.is_some();
+                let upvalue = position.unwrap_or_else(|| {
                     self.lambda.captures.push(captured);
                     self.lambda.captures.len() - 1
-                } else {
-                    self.lambda.captures.iter().position(|c| c == &captured).unwrap()
-                };
+                });
                 return Some((Captured::Nonlocal(upvalue), already));
             }
         }
@@ -165,6 +406,14 @@ impl Compiler {
         return None
     }
 
+    /// Looks up a host function in the base compiler's `FFI`.
+    pub fn host(&self, name: &str) -> Option<FFIFunction> {
+        match &self.enclosing {
+            Some(enclosing) => enclosing.host(name),
+            None => self.ffi.get(name).cloned(),
+        }
+    }
+
     /// returns the index of a captured non-local.
     pub fn captured_upvalue(&mut self, name: &str) -> Option<usize> {
         match self.captured(name) {
@@ -173,6 +422,58 @@ impl Compiler {
         }
     }
 
+    /// Collects the names of all variables that can be referenced in the current scope,
+    /// i.e. the locals in this scope and those that can be captured from enclosing ones.
+    pub fn in_scope(&self, names: &mut Vec<String>) {
+        for local in self.locals.iter() {
+            // names starting with '#' are made by the compiler, and can't be written
+            if local.name.starts_with('#') { continue; }
+            if !names.contains(&local.name) { names.push(local.name.clone()); }
+        }
+
+        if let Some(enclosing) = &self.enclosing { enclosing.in_scope(names); }
+    }
+
+    /// Records that a variable can not be resolved,
+    /// so compilation can continue to find other undefined variables.
+    pub fn report(&mut self, error: Syntax) {
+        match &mut self.enclosing {
+            Some(enclosing) => enclosing.report(error),
+            None => self.undefined.push(error),
+        }
+    }
+
+    /// Builds the error raised when a variable can not be resolved,
+    /// suggesting the variables in scope with the most similar names.
+    pub fn undefined(&self, name: &str, span: &Span) -> Syntax {
+        let mut names = vec![];
+        self.in_scope(&mut names);
+
+        // names that are too different aren't worth suggesting
+        let limit = name.chars().count().max(3) / 3;
+        let mut similar = names.into_iter()
+            .map(|n| (edit_distance(name, &n), n))
+            .filter(|(distance, _)| *distance <= limit)
+            .collect::<Vec<_>>();
+        similar.sort();
+
+        let closest = similar.first().map(|(distance, _)| *distance);
+        let suggestions = similar.into_iter()
+            .take_while(|(distance, _)| Some(*distance) == closest)
+            .take(3)
+            .map(|(_, n)| format!("'{}'", n))
+            .collect::<Vec<_>>();
+
+        let message = format!("The variable '{}' is undefined", name);
+        let message = match suggestions.split_last() {
+            None => message,
+            Some((last, [])) => format!("{}; did you mean {}?", message, last),
+            Some((last, rest)) => format!("{}; did you mean {} or {}?", message, rest.join(", "), last),
+        };
+
+        Syntax::error(&message, span)
+    }
+
     // TODO: rewrite according to new local rules
     /// Takes a symbol leaf, and produces some code to load the local
     pub fn symbol(&mut self, name: &str, span: Span) -> Result<(), Syntax> {
@@ -184,39 +485,74 @@ impl Compiler {
             // if the variable is captured in a closure
             self.lambda.emit(Opcode::LoadCap);
             self.lambda.emit_bytes(&mut split_number(upvalue))
+        } else if let Some(function) = self.host(name) {
+            // if the variable is a host function
+            self.data(Data::Host(function));
         } else {
-            // TODO: hoist?
-            return Err(Syntax::error(
-                "Variable referenced before assignment; it is undefined", &span
-            ));
+            // variables assigned to anywhere in scope have been hoisted,
+            // so the variable is not defined at all
+            let error = self.undefined(name, &span);
+            self.report(error);
+            self.data(Data::Unit);
         }
         Ok(())
     }
 
     /// A block is a series of expressions where the last is returned.
     /// Each sup-expression is walked, the last value is left on the stack.
-    pub fn block(&mut self, children: Vec<Spanned<CST>>) -> Result<(), Syntax> {
-        if children.is_empty() {
-            self.data(Data::Unit);
-            return Ok(());
-        }
+    /// The last expression is in tail position if the block is.
+    pub fn block(&mut self, mut children: Vec<Spanned<CST>>, tail: bool) -> Result<(), Syntax> {
+        let last = match children.pop() {
+            Some(last) => last,
+            None => {
+                self.data(Data::Unit);
+                return Ok(());
+            },
+        };
 
         for child in children {
             self.walk(&child)?;
             self.lambda.emit(Opcode::Del);
         }
 
-        // remove the last delete instruction
-        self.lambda.demit();
-        Ok(())
+        self.walk_in(&last, tail)
     }
 
-    pub fn print(&mut self, expression: Spanned<CST>) -> Result<(), Syntax> {
+    pub fn print(&mut self, expression: Spanned<CST>, span: Span) -> Result<(), Syntax> {
         self.walk(&expression)?;
+        self.lambda.emit_span(&span);
         self.lambda.emit(Opcode::Print);
         Ok(())
     }
 
+    /// Compiles the lambda a fiber wraps, then wraps it in a fiber.
+    pub fn fiber(&mut self, lambda: Spanned<CST>) -> Result<(), Syntax> {
+        self.walk(&lambda)?;
+        self.lambda.emit(Opcode::Fiber);
+        Ok(())
+    }
+
+    pub fn yield_(&mut self, expression: Spanned<CST>, span: Span) -> Result<(), Syntax> {
+        self.walk(&expression)?;
+        self.lambda.emit_span(&span);
+        self.lambda.emit(Opcode::Yield);
+        Ok(())
+    }
+
+    pub fn error(&mut self, expression: Spanned<CST>, span: Span) -> Result<(), Syntax> {
+        self.walk(&expression)?;
+        self.lambda.emit_span(&span);
+        self.lambda.emit(Opcode::Raise);
+        Ok(())
+    }
+
+    /// Compiles the lambda being tried, then runs it in a new fiber.
+    pub fn try_(&mut self, lambda: Spanned<CST>) -> Result<(), Syntax> {
+        self.walk(&lambda)?;
+        self.lambda.emit(Opcode::Try);
+        Ok(())
+    }
+
     pub fn label(&mut self, name: String, expression: Spanned<CST>) -> Result<(), Syntax> {
         self.walk(&expression)?;
         self.data(Data::Kind(name));
@@ -224,15 +560,203 @@ impl Compiler {
         Ok(())
     }
 
+    /// Builds a tuple out of a series of expressions.
+    /// Each item is pushed onto the stack in order,
+    /// and are then collected into a tuple.
+    pub fn tuple(&mut self, items: Vec<Spanned<CST>>) -> Result<(), Syntax> {
+        let length = items.len();
+        for item in items {
+            self.walk(&item)?;
+        }
+
+        self.lambda.emit(Opcode::Tuple);
+        self.lambda.emit_bytes(&mut split_number(length));
+        Ok(())
+    }
+
+    /// Builds a list out of a series of expressions.
+    /// Like tuples, each item is pushed onto the stack in order,
+    /// and are then collected into a list.
+    pub fn list(&mut self, items: Vec<Spanned<CST>>) -> Result<(), Syntax> {
+        let length = items.len();
+        for item in items {
+            self.walk(&item)?;
+        }
+
+        self.lambda.emit(Opcode::List);
+        self.lambda.emit_bytes(&mut split_number(length));
+        Ok(())
+    }
+
+    /// Prepends an item to the front of a list.
+    /// The tail is only known to be a list at runtime.
+    pub fn cons(&mut self, head: Spanned<CST>, tail: Spanned<CST>, span: Span) -> Result<(), Syntax> {
+        self.walk(&head)?;
+        self.walk(&tail)?;
+        self.lambda.emit_span(&span);
+        self.lambda.emit(Opcode::Cons);
+        Ok(())
+    }
+
+    /// Applies a binary operator to two expressions.
+    /// The left expression is pushed first, so the right one is on top.
+    /// Operators check the types of their operands at runtime.
+    pub fn binop(&mut self, op: BinOp, left: Spanned<CST>, right: Spanned<CST>, span: Span) -> Result<(), Syntax> {
+        if let BinOp::And | BinOp::Or = op {
+            return self.logical(op, left, right);
+        }
+
+        self.walk(&left)?;
+        self.walk(&right)?;
+        self.lambda.emit_span(&span);
+        self.lambda.emit(op.opcode());
+        Ok(())
+    }
+
+    /// Compiles `and` and `or`, which short-circuit:
+    /// the right operand is only run if the left one doesn't decide the result.
+    /// For `or`, each operand is negated, so in both cases
+    /// an operand that decides the result jumps to the same exit,
+    /// which pushes `false` for `and` and `true` for `or`.
+    pub fn logical(&mut self, op: BinOp, left: Spanned<CST>, right: Spanned<CST>) -> Result<(), Syntax> {
+        let or = op == BinOp::Or;
+        let mut decided = vec![];
+
+        for operand in [left, right].iter() {
+            self.walk(operand)?;
+            self.lambda.emit_span(&operand.span);
+            if or { self.lambda.emit(Opcode::Not); }
+            decided.push(self.lambda.emit_jump(Opcode::JumpFalse));
+        }
+
+        self.data(Data::Boolean(!or));
+        let end = self.lambda.emit_jump(Opcode::Jump);
+        for jump in decided { self.lambda.patch_jump(jump); }
+        self.data(Data::Boolean(or));
+        self.lambda.patch_jump(end);
+        Ok(())
+    }
+
+    /// Negates a boolean expression.
+    pub fn not(&mut self, expression: Spanned<CST>, span: Span) -> Result<(), Syntax> {
+        self.walk(&expression)?;
+        self.lambda.emit_span(&span);
+        self.lambda.emit(Opcode::Not);
+        Ok(())
+    }
+
+    /// Negates a numeric expression.
+    pub fn neg(&mut self, expression: Spanned<CST>, span: Span) -> Result<(), Syntax> {
+        self.walk(&expression)?;
+        self.lambda.emit_span(&span);
+        self.lambda.emit(Opcode::Neg);
+        Ok(())
+    }
+
+    /// Compiles a conditional.
+    /// If the condition is false, the first jump skips the `then` branch;
+    /// otherwise, the second jump skips the `otherwise` branch
+    /// after the `then` branch is run.
+    pub fn if_else(
+        &mut self,
+        condition: Spanned<CST>,
+        then:      Spanned<CST>,
+        otherwise: Spanned<CST>,
+        tail:      bool,
+    ) -> Result<(), Syntax> {
+        self.walk(&condition)?;
+        self.lambda.emit_span(&condition.span);
+        let skip_then = self.lambda.emit_jump(Opcode::JumpFalse);
+
+        self.walk_in(&then, tail)?;
+        let skip_otherwise = self.lambda.emit_jump(Opcode::Jump);
+
+        self.lambda.patch_jump(skip_then);
+        self.walk_in(&otherwise, tail)?;
+        self.lambda.patch_jump(skip_otherwise);
+        Ok(())
+    }
+
+    /// Compiles a match expression.
+    /// Before each arm is tried, a handler is installed,
+    /// so if the arm's pattern doesn't match,
+    /// the VM jumps to the next arm rather than raising an error.
+    /// If no arms match, a non-exhaustive match error is raised
+    /// at the span of the matched value.
+    /// Each arm binds its pattern to locals of its own,
+    /// which shadow variables in the enclosing scope and are hidden after the arm,
+    /// so the bindings of an arm that fails partway never leak into the next.
+    pub fn match_(
+        &mut self,
+        value: Spanned<CST>,
+        arms:  Vec<(Spanned<CSTPattern>, Spanned<CST>)>,
+        tail:  bool,
+    ) -> Result<(), Syntax> {
+        self.walk(&value)?;
+        let mut ends = vec![];
+
+        for (pattern, expression) in arms {
+            let mut bound = vec![];
+            Compiler::bindings(&pattern.item, &mut bound);
+            let opened = self.open_arm(bound);
+
+            // the value is copied so it's still there if the arm fails
+            let next_arm = self.lambda.emit_jump(Opcode::Handle);
+            self.lambda.emit(Opcode::Copy);
+            self.destructure(pattern)?;
+            self.lambda.emit(Opcode::Unhandle);
+
+            self.lambda.emit(Opcode::Del);
+            self.walk_in(&expression, tail)?;
+            ends.push(self.lambda.emit_jump(Opcode::Jump));
+            self.lambda.patch_jump(next_arm);
+            self.close_arm(opened);
+        }
+
+        self.lambda.emit_span(&value.span);
+        self.lambda.emit(Opcode::NoMatch);
+        for end in ends { self.lambda.patch_jump(end); }
+        Ok(())
+    }
+
+    /// Returns a tuple containing the names of a set of record fields.
+    /// This is used to describe the shape of a record in bytecode.
+    pub fn field_names<T>(fields: &[(String, T)]) -> Data {
+        Data::Tuple(fields.iter().map(|(f, _)| Data::String(f.clone())).collect())
+    }
+
+    /// Builds a record out of a series of fields.
+    /// Each value is pushed onto the stack in order,
+    /// followed by a tuple of the field names.
+    pub fn record(&mut self, fields: Vec<(String, Spanned<CST>)>) -> Result<(), Syntax> {
+        let names = Compiler::field_names(&fields);
+        for (_, value) in fields {
+            self.walk(&value)?;
+        }
+
+        self.data(names);
+        self.lambda.emit(Opcode::Record);
+        Ok(())
+    }
+
+    /// Looks up the value of a field in a record.
+    pub fn index(&mut self, expression: Spanned<CST>, field: String, span: Span) -> Result<(), Syntax> {
+        self.walk(&expression)?;
+        self.data(Data::String(field));
+        self.lambda.emit_span(&span);
+        self.lambda.emit(Opcode::Index);
+        Ok(())
+    }
+
+    /// Saves the topmost value on the stack into a variable.
+    /// Note that all variables should've already been declared when hoisted.
     pub fn resolve_assign(&mut self, name: &str) {
         let index = if let Some(i) = self.local(name) {
             self.lambda.emit(Opcode::Save); i
         } else if let Some(i) = self.captured_upvalue(name) {
             self.lambda.emit(Opcode::SaveCap); i
         } else {
-            self.declare(name.to_string());
-            self.lambda.emit(Opcode::Save);
-            self.locals.len() - 1
+            unreachable!("Variable '{}' was assigned to before it was hoisted", name);
         };
 
         self.lambda.emit_bytes(&mut split_number(index));
@@ -242,14 +766,16 @@ impl Compiler {
     /// a series of unpack and assign instructions.
     /// Instructions match against the topmost stack item.
     /// Does delete the data that is matched against.
-    pub fn destructure(&mut self, pattern: Spanned<CSTPattern>, redeclare: bool) {
+    pub fn destructure(&mut self, pattern: Spanned<CSTPattern>) -> Result<(), Syntax> {
         self.lambda.emit_span(&pattern.span);
 
         match pattern.item {
             CSTPattern::Symbol(name) => {
-                if redeclare { self.declare(name.to_string()) }
                 self.resolve_assign(&name);
             }
+            CSTPattern::Discard => {
+                self.lambda.emit(Opcode::Del);
+            }
             CSTPattern::Data(expected) => {
                 self.data(expected);
                 self.lambda.emit(Opcode::UnData);
@@ -257,9 +783,53 @@ impl Compiler {
             CSTPattern::Label(name, pattern) => {
                 self.data(Data::Kind(name));
                 self.lambda.emit(Opcode::UnLabel);
-                self.destructure(*pattern, redeclare);
+                self.destructure(*pattern)?;
+            }
+            CSTPattern::Tuple(patterns) => {
+                // the tuple's items are unpacked in reverse,
+                // so that the first item is on the top of the stack.
+                self.lambda.emit(Opcode::UnTuple);
+                self.lambda.emit_bytes(&mut split_number(patterns.len()));
+                for pattern in patterns {
+                    self.destructure(pattern)?;
+                }
+            }
+            CSTPattern::Record(fields) => {
+                // like tuples, the fields are unpacked so the first is on top.
+                self.data(Compiler::field_names(&fields));
+                self.lambda.emit(Opcode::UnRecord);
+                for (_, pattern) in fields {
+                    self.destructure(pattern)?;
+                }
+            }
+            CSTPattern::List(patterns) => {
+                self.lambda.emit(Opcode::UnList);
+                self.lambda.emit_bytes(&mut split_number(patterns.len()));
+                for pattern in patterns {
+                    self.destructure(pattern)?;
+                }
+            }
+            CSTPattern::Cons { head, tail } => {
+                // the head is unpacked on top of the tail.
+                self.lambda.emit(Opcode::UnCons);
+                self.destructure(*head)?;
+                self.destructure(*tail)?;
+            }
+            CSTPattern::Where { pattern, guard } => {
+                // the guard can use the bindings made by the pattern.
+                // if the guard is false, the pattern fails to match.
+                self.destructure(*pattern)?;
+                self.walk(&guard)?;
+                self.lambda.emit_span(&guard.span);
+                let fail = self.lambda.emit_jump(Opcode::JumpFalse);
+                let pass = self.lambda.emit_jump(Opcode::Jump);
+                self.lambda.patch_jump(fail);
+                self.lambda.emit(Opcode::Fail);
+                self.lambda.patch_jump(pass);
             }
         }
+
+        Ok(())
     }
 
     /// Assign a value to a variable.
@@ -270,7 +840,7 @@ impl Compiler {
     ) -> Result<(), Syntax> {
         // eval the expression
         self.walk(&expression)?;
-        self.destructure(pattern, false);
+        self.destructure(pattern)?;
         // self.lambda.emit(Opcode::Del);
         self.data(Data::Unit);
         Ok(())
@@ -286,12 +856,37 @@ impl Compiler {
         // just so the parallel is visually apparent
         self.enter_scope();
         {
+            // the argument is already on the stack, so it's the first local.
+            // if the pattern is a symbol, the argument is bound in place;
+            // otherwise, it's hidden and the pattern's variables are declared.
+            let mut bindings = vec![];
+            Compiler::bindings(&pattern.item, &mut bindings);
+            let simple = matches!(pattern.item, CSTPattern::Symbol(_));
+
+            let declared = if simple {
+                self.declare(bindings.pop().unwrap());
+                0
+            } else {
+                self.declare("#arg".to_string());
+                let count = bindings.len();
+                for name in bindings { self.declare(name); }
+                count
+            };
+
+            let mut declarations = vec![];
+            Compiler::declarations(&expression.item, &mut declarations);
+            let hoisted = self.hoist(declarations);
+            self.reserve(declared + hoisted);
+
             // match the argument against the pattern, binding variables
-            self.destructure(pattern, true);
-            // self.lambda.emit(Opcode::Del);
+            if !simple {
+                self.lambda.emit(Opcode::Load);
+                self.lambda.emit_bytes(&mut split_number(0));
+                self.destructure(pattern)?;
+            }
 
             // enter a new scope and walk the function body
-            self.walk(&expression)?;          // run the function
+            self.walk_tail(&expression)?;     // run the function
             self.lambda.emit(Opcode::Return); // return the result
             self.lambda.emit_bytes(&mut split_number(self.locals.len()));
         }
@@ -315,6 +910,18 @@ impl Compiler {
         self.lambda.emit(Opcode::Call);
         Ok(())
     }
+
+    /// A call in tail position replaces the frame of the current function.
+    /// If what's called isn't a closure, `TailCall` acts like a normal `Call`,
+    /// and the function returns afterwards as usual.
+    pub fn tail_call(&mut self, fun: Spanned<CST>, arg: Spanned<CST>) -> Result<(), Syntax> {
+        self.walk(&arg)?;
+        self.walk(&fun)?;
+
+        self.lambda.emit_span(&Span::combine(&fun.span, &arg.span));
+        self.lambda.emit(Opcode::TailCall);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -349,6 +956,7 @@ mod test {
         let lambda = gen(desugar(parse(lex(source).unwrap()).unwrap()).unwrap()).unwrap();
 
         let result = vec![
+            (Opcode::Reserve as u8), 131,                         // declare heck, lol, lmao
             (Opcode::Con as u8), 128, (Opcode::Save as u8), 128,  // con true, save to heck,
                 (Opcode::Con as u8), 129, (Opcode::Del as u8),    // load unit, delete
             (Opcode::Load as u8), 128, (Opcode::Save as u8), 129, // load heck, save to lol,
@@ -360,6 +968,56 @@ mod test {
         assert_eq!(result, lambda.code);
     }
 
+    #[test]
+    fn distance() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+        assert_eq!(edit_distance("count", "conut"), 1);
+        // parts of the string are only edited once
+        assert_eq!(edit_distance("ca", "abc"), 3);
+    }
+
+    #[test]
+    fn undefined() {
+        let cases = [
+            ("x = 1.0; total", "The variable 'total' is undefined"),
+            ("counter = 0.0; f = x -> x + conter", "The variable 'conter' is undefined; did you mean 'counter'?"),
+            ("bat = 1.0; cat = 2.0; rat", "The variable 'rat' is undefined; did you mean 'bat' or 'cat'?"),
+            ("length = 1.0; f = list -> { size = 2.0; g = n -> lenght + n }", "The variable 'lenght' is undefined; did you mean 'length'?"),
+            ("count = 0.0; f = () -> cuont", "The variable 'cuont' is undefined; did you mean 'count'?"),
+            // locals made by the compiler aren't suggested
+            ("match 1.0 { n -> arm; m -> m }", "The variable 'arm' is undefined"),
+            ("x = match 1.0 { n -> n }; closed", "The variable 'closed' is undefined"),
+            ("f = (a, b) -> arg", "The variable 'arg' is undefined"),
+        ];
+
+        for (code, message) in cases.iter() {
+            let source = Source::source(code);
+            let error = gen(desugar(parse(lex(source.clone()).unwrap()).unwrap()).unwrap()).unwrap_err();
+            assert_eq!(error.message, *message);
+
+            let name = message.split('\'').nth(1).unwrap();
+            assert_eq!(error.span, Span::new(&source, code.rfind(name).unwrap(), name.len()));
+        }
+    }
+
+    #[test]
+    fn undefined_all() {
+        let code = "x = 1.0\nfoo + bar + baz";
+        let source = Source::source(code);
+        let error = gen(desugar(parse(lex(source.clone()).unwrap()).unwrap()).unwrap()).unwrap_err();
+
+        let errors = std::iter::once(&error).chain(error.others.iter()).collect::<Vec<_>>();
+        assert_eq!(errors.len(), 3);
+
+        // each error points at its own use
+        for (error, name) in errors.into_iter().zip(["foo", "bar", "baz"].iter()) {
+            assert_eq!(error.message, format!("The variable '{}' is undefined", name));
+            assert_eq!(error.span, Span::new(&source, code.find(name).unwrap(), name.len()));
+        }
+    }
+
     // NOTE: instead of veryfying bytecode output,
     // write a test in vm::vm::test
     // and check behaviour that way
@@ -13,9 +13,11 @@ use crate::common::{
 use crate::compiler::{
     token::Token,
     syntax::Syntax,
+    operator::BinOp,
 };
 
 type Bite = (Token, usize);
+type Rule = Box<dyn Fn(&str) -> Result<Bite, String>>;
 
 /// Simple function that lexes a source file into a token stream.
 /// Exposes the functionality of the `Lexer`.
@@ -45,13 +47,13 @@ impl Lexer {
     pub fn all(&mut self) -> Result<Vec<Spanned<Token>>, Syntax> {
         let mut tokens = vec![];
 
-        while self.remaining().len() != 0 {
+        while !self.remaining().is_empty() {
             // strip preceeding whitespace
             self.strip();
 
             // clear out comments
-            self.offset += Lexer::comment(&self.remaining());
-            self.offset += Lexer::multi_comment(&self.remaining());
+            self.offset += Lexer::comment(self.remaining());
+            self.offset += Lexer::multi_comment(self.remaining());
 
             // strip trailing whitespace
             self.strip();
@@ -81,7 +83,7 @@ impl Lexer {
     pub fn step(&self) -> Result<Bite, String> {
         let source = self.remaining();
 
-        let rules: Vec<Box<dyn Fn(&str) -> Result<Bite, String>>> = vec![
+        let rules: Vec<Rule> = vec![
             // higher up in order = higher precedence
             // think 'or' as literal or 'or' as operator
 
@@ -91,10 +93,26 @@ impl Lexer {
             Box::new(Lexer::close_bracket),
             Box::new(Lexer::open_paren),
             Box::new(Lexer::close_paren),
+            Box::new(Lexer::open_square),
+            Box::new(Lexer::close_square),
+            Box::new(Lexer::pair),
+            Box::new(Lexer::colon),
+            Box::new(Lexer::dot),
+            Box::new(Lexer::cons),
+            Box::new(Lexer::where_),
             Box::new(Lexer::syntax),
             Box::new(Lexer::assign),
             Box::new(Lexer::lambda),
             Box::new(Lexer::compose),
+            Box::new(Lexer::operator),
+            Box::new(Lexer::not),
+            Box::new(Lexer::if_),
+            Box::new(Lexer::else_),
+            Box::new(Lexer::match_),
+            Box::new(Lexer::fiber),
+            Box::new(Lexer::yield_),
+            Box::new(Lexer::error),
+            Box::new(Lexer::try_),
             Box::new(Lexer::print), // remove print statements after FFI
 
             // variants
@@ -170,7 +188,7 @@ impl Lexer {
 
         for char in source.chars() {
             match char {
-                n if n.is_digit(10) => len += n.len_utf8(),
+                n if n.is_ascii_digit() => len += n.len_utf8(),
                 _                   => break,
             }
         }
@@ -210,6 +228,41 @@ impl Lexer {
         Lexer::literal(source, ")", Token::CloseParen)
     }
 
+    /// Matches a literal opening square bracket `[`.
+    pub fn open_square(source: &str) -> Result<Bite, String> {
+        Lexer::literal(source, "[", Token::OpenSquare)
+    }
+
+    /// Matches a literal closing square bracket `]`.
+    pub fn close_square(source: &str) -> Result<Bite, String> {
+        Lexer::literal(source, "]", Token::CloseSquare)
+    }
+
+    /// Matches a literal ampersand `&`, which separates a list's head from its tail.
+    pub fn cons(source: &str) -> Result<Bite, String> {
+        Lexer::literal(source, "&", Token::Cons)
+    }
+
+    /// Matches a literal bar `|`, which separates a pattern from its guard.
+    pub fn where_(source: &str) -> Result<Bite, String> {
+        Lexer::literal(source, "|", Token::Where)
+    }
+
+    /// Matches a literal comma `,`, used to build tuples.
+    pub fn pair(source: &str) -> Result<Bite, String> {
+        Lexer::literal(source, ",", Token::Pair)
+    }
+
+    /// Matches a literal colon `:`, used to define record fields.
+    pub fn colon(source: &str) -> Result<Bite, String> {
+        Lexer::literal(source, ":", Token::Colon)
+    }
+
+    /// Matches a literal dot `.`, the indexing operator.
+    pub fn dot(source: &str) -> Result<Bite, String> {
+        Lexer::literal(source, ".", Token::Dot)
+    }
+
     /// Matches a macro definition, `syntax`.
     pub fn syntax(source: &str) -> Result<Bite, String> {
         Lexer::literal(source, "syntax", Token::Syntax)
@@ -230,6 +283,63 @@ impl Lexer {
         Lexer::literal(source, "|>", Token::Compose)
     }
 
+    /// Matches a binary operator, like `+` or `and`.
+    /// The longest operator wins, so `<=` isn't lexed as `<`.
+    pub fn operator(source: &str) -> Result<Bite, String> {
+        let mut best: Option<Bite> = None;
+
+        for op in BinOp::ALL.iter() {
+            if let Ok(len) = Lexer::expect(source, op.literal()) {
+                let longer = match &best { Some((_, l)) => len > *l, None => true };
+                if longer {
+                    best = Some((Token::BinOp(*op), len));
+                }
+            }
+        }
+
+        return best.ok_or_else(|| "Expected an operator".to_string());
+    }
+
+    /// Matches a boolean `not`.
+    pub fn not(source: &str) -> Result<Bite, String> {
+        Lexer::literal(source, "not", Token::Not)
+    }
+
+    /// Matches the start of a conditional, `if`.
+    pub fn if_(source: &str) -> Result<Bite, String> {
+        Lexer::literal(source, "if", Token::If)
+    }
+
+    /// Matches the alternative branch of a conditional, `else`.
+    pub fn else_(source: &str) -> Result<Bite, String> {
+        Lexer::literal(source, "else", Token::Else)
+    }
+
+    /// Matches the start of a match expression, `match`.
+    pub fn match_(source: &str) -> Result<Bite, String> {
+        Lexer::literal(source, "match", Token::Match)
+    }
+
+    /// Matches the creation of a fiber, `fiber`.
+    pub fn fiber(source: &str) -> Result<Bite, String> {
+        Lexer::literal(source, "fiber", Token::Fiber)
+    }
+
+    /// Matches a `yield` expression.
+    pub fn yield_(source: &str) -> Result<Bite, String> {
+        Lexer::literal(source, "yield", Token::Yield)
+    }
+
+    /// Matches the raising of an error, `error`.
+    pub fn error(source: &str) -> Result<Bite, String> {
+        Lexer::literal(source, "error", Token::Error)
+    }
+
+    /// Matches a `try` expression.
+    pub fn try_(source: &str) -> Result<Bite, String> {
+        Lexer::literal(source, "try", Token::Try)
+    }
+
     /// Matches a `print` expression.
     pub fn print(source: &str) -> Result<Bite, String> {
         Lexer::literal(source, "print", Token::Print)
@@ -258,7 +368,7 @@ impl Lexer {
         };
 
         for char in source[len..].chars() {
-            if let Ok(_) = Lexer::expect(&source[len..], "-{") {
+            if Lexer::expect(&source[len..], "-{").is_ok() {
                 len += Lexer::multi_comment(&source[len..]);
             } else if let Ok(end) = Lexer::expect(&source[len..], "}-") {
                 len += end; break;
@@ -310,19 +420,28 @@ impl Lexer {
 
     /// Classifies a label (i.e. data wrapper).
     /// Must start with an uppercase character.
+    /// Labels may be qualified by other labels, i.e. `Result.Ok`.
     pub fn label(source: &str) -> Result<Bite, String> {
-        if let label @ (Token::Label, _) = Lexer::identifier(source)? {
-            Ok(label)
-        } else {
-            Err("Expected a Label".to_string())
+        let mut len = match Lexer::identifier(source)? {
+            (Token::Label, len) => len,
+            _ => return Err("Expected a Label".to_string()),
+        };
+
+        while source[len..].starts_with('.') {
+            match Lexer::identifier(&source[len + 1..]) {
+                Ok((Token::Label, qualified)) => len += 1 + qualified,
+                _ => break,
+            }
         }
+
+        Ok((Token::Label, len))
     }
 
     /// Classifies a pseudokeyword, used in syntax macros.
     /// Must start with a single quote `'`.
     pub fn keyword(source: &str) -> Result<Bite, String> {
         let mut len = 0;
-        len += Lexer::expect(&source, "'")?;
+        len += Lexer::expect(source, "'")?;
 
         if let (Token::Symbol, l) = Lexer::identifier(&source[len..])? {
             let keyword = source[len..len+l].to_string();
@@ -538,6 +657,128 @@ mod test {
         if !test_literal("false", Token::Boolean(Data::Boolean(false)), 5) { panic!() }
     }
 
+    #[test]
+    fn pair() {
+        if !test_literal(",", Token::Pair, 1) { panic!() }
+    }
+
+    #[test]
+    fn index() {
+        let source = Source::source("isaac.age");
+
+        let result = vec![
+            Spanned::new(Token::Symbol, Span::new(&source, 0, 5)),
+            Spanned::new(Token::Dot,    Span::new(&source, 5, 1)),
+            Spanned::new(Token::Symbol, Span::new(&source, 6, 3)),
+            Spanned::new(Token::End,    Span::empty()),
+        ];
+
+        assert_eq!(lex(source), Ok(result));
+    }
+
+    #[test]
+    fn list() {
+        let source = Source::source("[a & b]");
+
+        let result = vec![
+            Spanned::new(Token::OpenSquare,  Span::new(&source, 0, 1)),
+            Spanned::new(Token::Symbol,      Span::new(&source, 1, 1)),
+            Spanned::new(Token::Cons,        Span::new(&source, 3, 1)),
+            Spanned::new(Token::Symbol,      Span::new(&source, 5, 1)),
+            Spanned::new(Token::CloseSquare, Span::new(&source, 6, 1)),
+            Spanned::new(Token::End,         Span::empty()),
+        ];
+
+        assert_eq!(lex(source), Ok(result));
+    }
+
+    #[test]
+    fn operators() {
+        let source = Source::source("a <= b + -c and not d");
+
+        let result = vec![
+            Spanned::new(Token::Symbol,                     Span::new(&source, 0,  1)),
+            Spanned::new(Token::BinOp(BinOp::LessEqual),    Span::new(&source, 2,  2)),
+            Spanned::new(Token::Symbol,                     Span::new(&source, 5,  1)),
+            Spanned::new(Token::BinOp(BinOp::Add),          Span::new(&source, 7,  1)),
+            Spanned::new(Token::BinOp(BinOp::Sub),          Span::new(&source, 9,  1)),
+            Spanned::new(Token::Symbol,                     Span::new(&source, 10, 1)),
+            Spanned::new(Token::BinOp(BinOp::And),          Span::new(&source, 12, 3)),
+            Spanned::new(Token::Not,                        Span::new(&source, 16, 3)),
+            Spanned::new(Token::Symbol,                     Span::new(&source, 20, 1)),
+            Spanned::new(Token::End,                        Span::empty()),
+        ];
+
+        assert_eq!(lex(source), Ok(result));
+    }
+
+    #[test]
+    fn operator_prefix_symbol() {
+        // symbols that start with an operator are still symbols
+        if !test_literal("android", Token::Symbol, 7) { panic!() }
+        if !test_literal("nothing", Token::Symbol, 7) { panic!() }
+    }
+
+    #[test]
+    fn conditional() {
+        let source = Source::source("if a {} else {}");
+
+        let result = vec![
+            Spanned::new(Token::If,           Span::new(&source, 0,  2)),
+            Spanned::new(Token::Symbol,       Span::new(&source, 3,  1)),
+            Spanned::new(Token::OpenBracket,  Span::new(&source, 5,  1)),
+            Spanned::new(Token::CloseBracket, Span::new(&source, 6,  1)),
+            Spanned::new(Token::Else,         Span::new(&source, 8,  4)),
+            Spanned::new(Token::OpenBracket,  Span::new(&source, 13, 1)),
+            Spanned::new(Token::CloseBracket, Span::new(&source, 14, 1)),
+            Spanned::new(Token::End,          Span::empty()),
+        ];
+
+        assert_eq!(lex(source), Ok(result));
+    }
+
+    #[test]
+    fn match_keyword() {
+        if !test_literal("match", Token::Match, 5) { panic!() }
+        if !test_literal("matches", Token::Symbol, 7) { panic!() }
+    }
+
+    #[test]
+    fn fiber_keywords() {
+        if !test_literal("fiber", Token::Fiber, 5) { panic!() }
+        if !test_literal("yield", Token::Yield, 5) { panic!() }
+        if !test_literal("yielded", Token::Symbol, 7) { panic!() }
+    }
+
+    #[test]
+    fn error_keywords() {
+        if !test_literal("error", Token::Error, 5) { panic!() }
+        if !test_literal("try", Token::Try, 3) { panic!() }
+        if !test_literal("trying", Token::Symbol, 6) { panic!() }
+    }
+
+    #[test]
+    fn qualified_label() {
+        if !test_literal("Result.Ok", Token::Label, 9) { panic!() }
+        if !test_literal("Result.ok", Token::Label, 6) { panic!() }
+    }
+
+    #[test]
+    fn guard() {
+        let source = Source::source("x | x |> f");
+
+        let result = vec![
+            Spanned::new(Token::Symbol,  Span::new(&source, 0, 1)),
+            Spanned::new(Token::Where,   Span::new(&source, 2, 1)),
+            Spanned::new(Token::Symbol,  Span::new(&source, 4, 1)),
+            Spanned::new(Token::Compose, Span::new(&source, 6, 2)),
+            Spanned::new(Token::Symbol,  Span::new(&source, 9, 1)),
+            Spanned::new(Token::End,     Span::empty()),
+        ];
+
+        assert_eq!(lex(source), Ok(result));
+    }
+
     #[test]
     fn assign() {
         if !test_literal("=", Token::Assign, 1) { panic!() }
@@ -22,6 +22,7 @@ pub mod token;
 pub mod ast; // high level pre-macro IR
 pub mod rule; // macro transformation
 pub mod cst; // post-macro IR
+pub mod operator;
 
 pub mod syntax;
 
@@ -0,0 +1,96 @@
+use std::fmt::Display;
+
+use crate::common::opcode::Opcode;
+
+/// Built-in binary operators, like `+` or `==`.
+/// The syntax trees store which operator is being applied,
+/// rather than having a separate variant for each one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    // Arithmetic
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+
+    // Comparison
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+
+    // Logical
+    And,
+    Or,
+}
+
+impl BinOp {
+    /// Every binary operator.
+    pub const ALL: [BinOp; 13] = [
+        BinOp::Add,
+        BinOp::Sub,
+        BinOp::Mul,
+        BinOp::Div,
+        BinOp::Rem,
+        BinOp::Equal,
+        BinOp::NotEqual,
+        BinOp::Less,
+        BinOp::LessEqual,
+        BinOp::Greater,
+        BinOp::GreaterEqual,
+        BinOp::And,
+        BinOp::Or,
+    ];
+
+    /// Returns how an operator is written in source.
+    pub fn literal(&self) -> &'static str {
+        match self {
+            BinOp::Add          => "+",
+            BinOp::Sub          => "-",
+            BinOp::Mul          => "*",
+            BinOp::Div          => "/",
+            BinOp::Rem          => "%",
+            BinOp::Equal        => "==",
+            BinOp::NotEqual     => "!=",
+            BinOp::Less         => "<",
+            BinOp::LessEqual    => "<=",
+            BinOp::Greater      => ">",
+            BinOp::GreaterEqual => ">=",
+            BinOp::And          => "and",
+            BinOp::Or           => "or",
+        }
+    }
+
+    /// Returns the opcode that applies this operator.
+    pub fn opcode(&self) -> Opcode {
+        match self {
+            BinOp::Add          => Opcode::Add,
+            BinOp::Sub          => Opcode::Sub,
+            BinOp::Mul          => Opcode::Mul,
+            BinOp::Div          => Opcode::Div,
+            BinOp::Rem          => Opcode::Rem,
+            BinOp::Equal        => Opcode::Equal,
+            BinOp::NotEqual     => Opcode::NotEqual,
+            BinOp::Less         => Opcode::Less,
+            BinOp::LessEqual    => Opcode::LessEqual,
+            BinOp::Greater      => Opcode::Greater,
+            BinOp::GreaterEqual => Opcode::GreaterEqual,
+            BinOp::And          => Opcode::And,
+            BinOp::Or           => Opcode::Or,
+        }
+    }
+
+    /// Returns the operator applied by an opcode, if there is one.
+    pub fn from_opcode(opcode: Opcode) -> Option<BinOp> {
+        BinOp::ALL.iter().copied().find(|op| op.opcode() == opcode)
+    }
+}
+
+impl Display for BinOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.literal())
+    }
+}
@@ -12,6 +12,7 @@ use crate::compiler::{
     syntax::Syntax,
     token::Token,
     ast::{AST, ASTPattern, ArgPat},
+    operator::BinOp,
 };
 
 /// Simple function that parses a token stream into an AST.
@@ -32,9 +33,19 @@ pub fn parse(tokens: Vec<Spanned<Token>>) -> Result<Spanned<AST>, Syntax> {
 pub enum Prec {
     None = 0,
     Assign,
+    Pair,
     Lambda,
+    Where,
     Compose,
+    Or,
+    And,
+    Not,
+    Equality,
+    Comparison,
+    Addition,
+    Multiplication,
     Call,
+    Index,
     End,
 }
 
@@ -48,7 +59,7 @@ impl Prec {
     /// By default, the parser accociates right.
     pub fn associate_left(&self) -> Prec {
         if let Prec::End = self { panic!("Can not associate further left") }
-        return unsafe { mem::transmute(self.clone() as u8 + 1) };
+        return unsafe { mem::transmute::<u8, Prec>(*self as u8 + 1) };
     }
 }
 
@@ -59,12 +70,16 @@ impl Prec {
 pub struct Parser {
     tokens: Vec<Spanned<Token>>,
     index:  usize,
+    /// Whether the condition of an `if` is being parsed.
+    /// A block after a condition starts the body of the `if`,
+    /// so it can't be passed to the condition as an argument.
+    condition: bool,
 }
 
 impl Parser {
     /// Create a new `parser`.
     pub fn new(tokens: Vec<Spanned<Token>>) -> Parser {
-        Parser { tokens, index: 0 }
+        Parser { tokens, index: 0, condition: false }
     }
 
     // Cookie Monster's Helper Functions:
@@ -140,11 +155,20 @@ impl Parser {
             Token::End         => Ok(Spanned::new(AST::Block(vec![]), Span::empty())),
 
             Token::Syntax      => self.syntax(),
-            Token::OpenParen   => self.group(),
-            Token::OpenBracket => self.block(),
+            Token::OpenParen   => self.delimited(Parser::group),
+            Token::OpenBracket => self.delimited(Parser::block),
+            Token::OpenSquare  => self.delimited(Parser::list),
+            Token::If          => self.if_else(),
+            Token::Match       => self.match_(),
             Token::Symbol      => self.symbol(),
             Token::Print       => self.print(),
+            Token::Fiber       => self.fiber(),
+            Token::Yield       => self.yield_(),
+            Token::Error       => self.error(),
+            Token::Try         => self.try_(),
             Token::Label       => self.label(),
+            Token::Not         => self.not(),
+            Token::BinOp(BinOp::Sub) => self.neg(),
             Token::Keyword(_)  => self.keyword(),
 
             Token::Unit
@@ -161,8 +185,12 @@ impl Parser {
     pub fn rule_infix(&mut self, left: Spanned<AST>) -> Result<Spanned<AST>, Syntax> {
         match self.skip().item {
             Token::Assign  => self.assign(left),
+            Token::Pair    => self.pair(left),
             Token::Lambda  => self.lambda(left),
+            Token::Where   => self.where_(left),
             Token::Compose => self.compose(left),
+            Token::Dot     => self.index(left),
+            Token::BinOp(o) => self.binop(left, o),
 
             Token::End    => Err(self.unexpected()),
             Token::Sep    => unreachable!(),
@@ -170,6 +198,31 @@ impl Parser {
         }
     }
 
+    /// Determines the precedence of a binary operator.
+    /// Multiplication binds tighter than addition,
+    /// which binds tighter than comparison, and so on.
+    pub fn binop_prec(op: BinOp) -> Prec {
+        match op {
+              BinOp::Mul
+            | BinOp::Div
+            | BinOp::Rem => Prec::Multiplication,
+
+              BinOp::Add
+            | BinOp::Sub => Prec::Addition,
+
+              BinOp::Less
+            | BinOp::LessEqual
+            | BinOp::Greater
+            | BinOp::GreaterEqual => Prec::Comparison,
+
+              BinOp::Equal
+            | BinOp::NotEqual => Prec::Equality,
+
+            BinOp::And => Prec::And,
+            BinOp::Or  => Prec::Or,
+        }
+    }
+
     /// Looks at the current operator token and determines the precedence
     pub fn prec(&mut self) -> Result<Prec, Syntax> {
         let next = self.draw().item.clone();
@@ -178,19 +231,35 @@ impl Parser {
 
         let prec = match next {
             Token::Assign  => Prec::Assign,
+            Token::Pair    => Prec::Pair,
             Token::Lambda  => Prec::Lambda,
+            Token::Where   => Prec::Where,
             Token::Compose => Prec::Compose,
+            Token::Dot     => Prec::Index,
+            Token::BinOp(o) => Parser::binop_prec(o),
 
               Token::End
+            | Token::Else
+            | Token::Colon
+            | Token::Cons
             | Token::CloseParen
-            | Token::CloseBracket => Prec::End,
+            | Token::CloseBracket
+            | Token::CloseSquare => Prec::End,
 
             // prefix rules
               Token::OpenParen
             | Token::OpenBracket
+            | Token::OpenSquare
             | Token::Unit
             | Token::Syntax
             | Token::Print
+            | Token::Fiber
+            | Token::Yield
+            | Token::Error
+            | Token::Try
+            | Token::Not
+            | Token::If
+            | Token::Match
             | Token::Symbol
             | Token::Keyword(_)
             | Token::Label
@@ -201,7 +270,10 @@ impl Parser {
             Token::Sep => unreachable!(),
         };
 
-        if sep && prec == Prec::Call {
+        if sep && next == Token::BinOp(BinOp::Sub) {
+            // a minus that starts a new line negates, rather than subtracts
+            Ok(Prec::End)
+        } else if (sep || (self.condition && next == Token::OpenBracket)) && prec == Prec::Call {
             Ok(Prec::End)
         } else {
             Ok(prec)
@@ -227,6 +299,19 @@ impl Parser {
         return Ok(left);
     }
 
+    /// Parses a construct between delimiters, like a group or a block.
+    /// Inside of delimiters, blocks can be passed as arguments again,
+    /// even if the delimiters are in the condition of an `if`.
+    pub fn delimited(
+        &mut self,
+        rule: fn(&mut Parser) -> Result<Spanned<AST>, Syntax>,
+    ) -> Result<Spanned<AST>, Syntax> {
+        let condition = mem::replace(&mut self.condition, false);
+        let result = rule(self);
+        self.condition = condition;
+        return result;
+    }
+
     // Rule Definitions:
 
     // Prefix:
@@ -259,7 +344,7 @@ impl Parser {
             Token::Boolean(b) => AST::Data(b.clone()),
             unexpected => return Err(Syntax::error(
                 &format!("Expected a literal, found {}", unexpected),
-                &span
+                span
             )),
         };
 
@@ -285,7 +370,7 @@ impl Parser {
         while self.skip().item != end {
             let ast = self.expression(Prec::None, false)?;
             expressions.push(ast);
-            if let Err(_) = self.consume(Token::Sep) {
+            if self.consume(Token::Sep).is_err() {
                 break;
             }
         }
@@ -296,13 +381,108 @@ impl Parser {
     /// Parse a block as an expression,
     /// Building the appropriate `AST`.
     /// Just a body between curlies.
+    /// If the curlies contain fields rather than expressions,
+    /// a record is parsed instead.
     pub fn block(&mut self) -> Result<Spanned<AST>, Syntax> {
+        if self.record_ahead() { return self.record(); }
+
         let start = self.consume(Token::OpenBracket)?.span.clone();
         let ast = self.body(Token::CloseBracket)?;
         let end = self.consume(Token::CloseBracket)?.span.clone();
         return Ok(Spanned::new(ast, Span::combine(&start, &end)));
     }
 
+    /// Looks ahead to check whether the current opening curly begins a record.
+    /// A record starts with a field name followed by a colon or a comma,
+    /// i.e. `{ name: ...` or `{ name, ...`.
+    pub fn record_ahead(&self) -> bool {
+        let mut offset = 1;
+        while self.tokens[self.index + offset].item == Token::Sep {
+            offset += 1;
+        }
+
+        if self.tokens[self.index + offset].item != Token::Symbol { return false; }
+        return matches!(self.tokens[self.index + offset + 1].item, Token::Colon | Token::Pair);
+    }
+
+    /// Parses a record, i.e. a series of fields between curlies.
+    /// Each field is either `name: expression` or just `name`,
+    /// which is short for `name: name`.
+    /// Fields are separated by commas, newlines, or both.
+    pub fn record(&mut self) -> Result<Spanned<AST>, Syntax> {
+        let start = self.consume(Token::OpenBracket)?.span.clone();
+        let mut fields: Vec<(String, Spanned<AST>)> = vec![];
+
+        while self.skip().item != Token::CloseBracket {
+            let name = self.consume(Token::Symbol)?.span.clone();
+            let field = name.contents();
+
+            let value = if self.current().item == Token::Colon {
+                self.consume(Token::Colon)?;
+                self.expression(Prec::Pair.associate_left(), false)?
+            } else {
+                Spanned::new(AST::Symbol(field.clone()), name.clone())
+            };
+
+            if fields.iter().any(|(f, _)| f == &field) {
+                return Err(Syntax::error(
+                    &format!("The field '{}' has already been defined in this record", field),
+                    &name,
+                ));
+            }
+            fields.push((field, value));
+
+            let comma = self.current().item == Token::Pair;
+            if comma { self.consume(Token::Pair)?; }
+            if !self.sep() && !comma { break; }
+        }
+
+        let end = self.consume(Token::CloseBracket)?.span.clone();
+        return Ok(Spanned::new(AST::Record(fields), Span::combine(&start, &end)));
+    }
+
+    /// Parses a list, i.e. a series of expressions separated by commas,
+    /// between square brackets.
+    /// The last item in a list may be followed by `& tail`,
+    /// where the tail is another list,
+    /// so `[a, b & c]` is the list `c` with `a` and `b` in front.
+    pub fn list(&mut self) -> Result<Spanned<AST>, Syntax> {
+        let start = self.consume(Token::OpenSquare)?.span.clone();
+        let mut items = vec![];
+        let mut tail  = None;
+
+        while self.skip().item != Token::CloseSquare {
+            items.push(self.expression(Prec::Pair.associate_left(), true)?);
+
+            if self.current().item == Token::Cons {
+                self.consume(Token::Cons)?;
+                tail = Some(self.expression(Prec::Pair.associate_left(), true)?);
+                break;
+            }
+
+            if self.current().item != Token::Pair { break; }
+            self.consume(Token::Pair)?;
+        }
+
+        let end  = self.skip().span.clone();
+        self.consume(Token::CloseSquare)?;
+        let span = Span::combine(&start, &end);
+
+        let mut list = match tail {
+            Some(tail) => tail,
+            None => return Ok(Spanned::new(AST::List(items), span)),
+        };
+
+        // build up the list from the tail, back to front
+        for item in items.into_iter().rev() {
+            let combined = Span::combine(&item.span, &list.span);
+            list = Spanned::new(AST::cons(item, list), combined);
+        }
+
+        list.span = span;
+        return Ok(list);
+    }
+
     // TODO: unwrap from outside in to prevent nesting
     /// Parse a macro definition.
     /// `syntax`, followed by a pattern, followed by a `block`
@@ -354,6 +534,58 @@ impl Parser {
         ))
     }
 
+    /// Parse a fiber.
+    /// A fiber takes the form `fiber <expression>`,
+    /// and runs the expression when it's first resumed.
+    pub fn fiber(&mut self) -> Result<Spanned<AST>, Syntax> {
+        let start = self.consume(Token::Fiber)?.span.clone();
+        let ast = self.expression(Prec::Call, false)?;
+        let end = ast.span.clone();
+        return Ok(Spanned::new(
+            AST::Fiber(Box::new(ast)),
+            Span::combine(&start, &end),
+        ))
+    }
+
+    /// Parse a yield.
+    /// A yield takes the form `yield <expression>`,
+    /// and evaluates to the value the fiber is next resumed with.
+    pub fn yield_(&mut self) -> Result<Spanned<AST>, Syntax> {
+        let start = self.consume(Token::Yield)?.span.clone();
+        let ast = self.expression(Prec::Call, false)?;
+        let end = ast.span.clone();
+        return Ok(Spanned::new(
+            AST::Yield(Box::new(ast)),
+            Span::combine(&start, &end),
+        ))
+    }
+
+    /// Parse an error.
+    /// An error takes the form `error <expression>`,
+    /// and crashes the current fiber with the value of the expression.
+    pub fn error(&mut self) -> Result<Spanned<AST>, Syntax> {
+        let start = self.consume(Token::Error)?.span.clone();
+        let ast = self.expression(Prec::Call, false)?;
+        let end = ast.span.clone();
+        return Ok(Spanned::new(
+            AST::Error(Box::new(ast)),
+            Span::combine(&start, &end),
+        ))
+    }
+
+    /// Parse a try.
+    /// A try takes the form `try <expression>`,
+    /// and evaluates the expression in a new fiber, catching any errors.
+    pub fn try_(&mut self) -> Result<Spanned<AST>, Syntax> {
+        let start = self.consume(Token::Try)?.span.clone();
+        let ast = self.expression(Prec::Call, false)?;
+        let end = ast.span.clone();
+        return Ok(Spanned::new(
+            AST::Try(Box::new(ast)),
+            Span::combine(&start, &end),
+        ))
+    }
+
     /// Parse a label.
     /// A label takes the form of `<Label> <expression>`
     pub fn label(&mut self) -> Result<Spanned<AST>, Syntax> {
@@ -366,6 +598,117 @@ impl Parser {
         ));
     }
 
+    /// Parses a boolean `not`.
+    /// `not` binds looser than comparisons but tighter than `and`,
+    /// so `not a == b and c` is `(not (a == b)) and c`.
+    pub fn not(&mut self) -> Result<Spanned<AST>, Syntax> {
+        let start = self.consume(Token::Not)?.span.clone();
+        let ast = self.expression(Prec::Not, false)?;
+        let end = ast.span.clone();
+        return Ok(Spanned::new(
+            AST::Not(Box::new(ast)),
+            Span::combine(&start, &end),
+        ));
+    }
+
+    /// Parses a unary minus.
+    /// It binds tighter than any binary operator but looser than a call,
+    /// so `-f x * y` is `(-(f x)) * y`.
+    pub fn neg(&mut self) -> Result<Spanned<AST>, Syntax> {
+        let start = self.consume(Token::BinOp(BinOp::Sub))?.span.clone();
+        let ast = self.expression(Prec::Call, false)?;
+        let end = ast.span.clone();
+        return Ok(Spanned::new(
+            AST::Neg(Box::new(ast)),
+            Span::combine(&start, &end),
+        ));
+    }
+
+    /// Parses a conditional, i.e. `if condition { ... } else { ... }`.
+    /// The `else` branch is optional, and may be another `if`.
+    /// If there's no `else` branch and the condition is false,
+    /// the conditional is Unit.
+    pub fn if_else(&mut self) -> Result<Spanned<AST>, Syntax> {
+        let start = self.consume(Token::If)?.span.clone();
+
+        let outer = mem::replace(&mut self.condition, true);
+        let condition = self.expression(Prec::None.associate_left(), false);
+        self.condition = outer;
+        let condition = condition?;
+
+        let then = self.branch()?;
+        let mut end = then.span.clone();
+
+        let otherwise = if self.draw().item == Token::Else {
+            self.skip();
+            self.consume(Token::Else)?;
+            let otherwise = if self.current().item == Token::If {
+                self.if_else()?
+            } else {
+                self.branch()?
+            };
+            end = otherwise.span.clone();
+            otherwise
+        } else {
+            Spanned::new(AST::Data(Data::Unit), Span::empty())
+        };
+
+        return Ok(Spanned::new(
+            AST::if_else(condition, then, otherwise),
+            Span::combine(&start, &end),
+        ));
+    }
+
+    /// Parses a match expression, i.e. `match value { pattern -> expression ... }`.
+    /// Each arm is written like a function, and arms are separated by newlines.
+    /// The arms are tried in order, and the first one whose pattern matches is taken.
+    pub fn match_(&mut self) -> Result<Spanned<AST>, Syntax> {
+        let start = self.consume(Token::Match)?.span.clone();
+
+        // like the condition of an `if`, the value is followed by a block
+        let outer = mem::replace(&mut self.condition, true);
+        let value = self.expression(Prec::None.associate_left(), false);
+        self.condition = outer;
+        let value = value?;
+
+        let block = self.branch()?;
+        let items = match block.item {
+            AST::Block(items) => items,
+            _ => return Err(Syntax::error(
+                "Expected the arms of the match, i.e. 'pattern -> expression'",
+                &block.span,
+            )),
+        };
+
+        let mut arms = vec![];
+        for item in items {
+            match item.item {
+                AST::Lambda { pattern, expression } => arms.push((*pattern, *expression)),
+                _ => return Err(Syntax::error(
+                    "Expected a match arm, i.e. 'pattern -> expression'",
+                    &item.span,
+                )),
+            }
+        }
+
+        return Ok(Spanned::new(
+            AST::match_(value, arms),
+            Span::combine(&start, &block.span),
+        ));
+    }
+
+    /// Parses a branch of a conditional, which must be a block.
+    pub fn branch(&mut self) -> Result<Spanned<AST>, Syntax> {
+        if self.skip().item != Token::OpenBracket {
+            return Err(Syntax::error(
+                "Expected a block after the condition",
+                &self.current().span,
+            ));
+        }
+
+        return self.delimited(Parser::block);
+    }
+
     // Infix:
 
     pub fn arg_pat(ast: Spanned<AST>) -> Result<Spanned<ArgPat>, Syntax> {
@@ -412,6 +755,64 @@ impl Parser {
         Ok(Spanned::new(AST::lambda(pattern, expression), combined))
     }
 
+    /// Parses a pattern with a guard, i.e. `pattern | expression`.
+    /// The pattern only matches if the guard is true.
+    /// Guards bind tighter than lambdas, so `x | x > 0.0 -> x` guards `x`.
+    pub fn where_(&mut self, left: Spanned<AST>) -> Result<Spanned<AST>, Syntax> {
+        let left_span = left.span.clone();
+        let pattern = left.map(ASTPattern::try_from)
+            .map_err(|e| Syntax::error(&e, &left_span))?;
+
+        self.consume(Token::Where)?;
+        let guard    = self.expression(Prec::Where.associate_left(), false)?;
+        let combined = Span::combine(&pattern.span, &guard.span);
+        Ok(Spanned::new(AST::Pattern(ASTPattern::where_(pattern, guard)), combined))
+    }
+
+    /// Parses a tuple, i.e. a series of expressions separated by commas.
+    /// The items of a tuple are collected all at once,
+    /// so that a nested tuple in parenthesis isn't flattened.
+    /// A trailing comma is allowed, so `(x,)` is a tuple with a single item.
+    pub fn pair(&mut self, left: Spanned<AST>) -> Result<Spanned<AST>, Syntax> {
+        let mut span  = left.span.clone();
+        let mut tuple = vec![left];
+
+        while self.current().item == Token::Pair {
+            let comma = self.consume(Token::Pair)?.span.clone();
+            span = Span::combine(&span, &comma);
+
+            // a tuple may be split across lines after a comma
+            if let Token::CloseParen
+                 | Token::CloseBracket
+                 | Token::End = self.draw().item { break; }
+
+            let item = self.expression(Prec::Pair.associate_left(), false)?;
+            span = Span::combine(&span, &item.span);
+            tuple.push(item);
+        }
+
+        return Ok(Spanned::new(AST::Tuple(tuple), span));
+    }
+
+    /// Parses the indexing operator, i.e. `record.field`.
+    /// Indexing binds tighter than function calls,
+    /// so `f a.b` is the same as `f (a.b)`.
+    pub fn index(&mut self, left: Spanned<AST>) -> Result<Spanned<AST>, Syntax> {
+        self.consume(Token::Dot)?;
+        let field    = self.consume(Token::Symbol)?.span.clone();
+        let combined = Span::combine(&left.span, &field);
+        return Ok(Spanned::new(AST::index(left, field.contents()), combined));
+    }
+
+    /// Parses a binary operator, like `a + b`.
+    /// Binary operators associate left, so `a - b - c` is `(a - b) - c`.
+    pub fn binop(&mut self, left: Spanned<AST>, op: BinOp) -> Result<Spanned<AST>, Syntax> {
+        self.consume(Token::BinOp(op))?;
+        let right = self.expression(Parser::binop_prec(op).associate_left(), false)?;
+        let combined = Span::combine(&left.span, &right.span);
+        return Ok(Spanned::new(AST::binop(op, left, right), combined));
+    }
+
     pub fn compose(&mut self, left: Spanned<AST>) -> Result<Spanned<AST>, Syntax> {
         self.consume(Token::Compose)?;
         let right = self.expression(Prec::Compose.associate_left(), false)?;
@@ -483,6 +884,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     pub fn lambda() {
         let source = Source::source("x = y -> 3.141592");
         let ast = parse(lex(source.clone()).unwrap()).unwrap();
@@ -514,4 +916,30 @@ mod test {
             )
         );
     }
+
+    #[test]
+    pub fn precedence() {
+        // `not` binds looser than `==`, which binds looser than `+`, and so on
+        let source = Source::source("not a + b * c == d and e");
+        let ast = parse(lex(source.clone()).unwrap()).unwrap();
+
+        fn show(ast: AST) -> String {
+            match ast {
+                AST::Symbol(s) => s,
+                AST::Not(e) => format!("(not {})", show(e.item)),
+                AST::Neg(e) => format!("(-{})", show(e.item)),
+                AST::BinOp { op, left, right } => format!(
+                    "({} {} {})", show(left.item), op, show(right.item),
+                ),
+                AST::Block(mut b) => show(b.remove(0).item),
+                _ => panic!("Unexpected construct"),
+            }
+        }
+
+        assert_eq!(show(ast.item), "((not ((a + (b * c)) == d)) and e)");
+
+        let source = Source::source("-a * b - -c");
+        let ast = parse(lex(source).unwrap()).unwrap();
+        assert_eq!(show(ast.item), "(((-a) * b) - (-c))");
+    }
 }
@@ -1,6 +1,6 @@
 use std::{
     convert::TryFrom,
-    collections::HashMap,
+    collections::{HashMap, hash_map::Entry},
 };
 
 use crate::common::{
@@ -31,7 +31,7 @@ impl Rule {
         arg_pat: Spanned<ArgPat>,
         tree: Spanned<AST>,
     ) -> Result<Rule, Syntax> {
-        if Rule::keywords(&arg_pat).len() == 0 {
+        if Rule::keywords(&arg_pat).is_empty() {
             return Err(Syntax::error(
                 "Syntactic macro must have at least one pseudokeyword",
                 &arg_pat.span,
@@ -46,7 +46,7 @@ impl Rule {
         match &arg_pat.item {
             ArgPat::Group(pats) => {
                 let mut keywords = vec![];
-                for pat in pats { keywords.append(&mut Rule::keywords(&pat)) }
+                for pat in pats { keywords.append(&mut Rule::keywords(pat)) }
                 keywords
             },
             ArgPat::Keyword(name) => vec![name.clone()],
@@ -63,8 +63,10 @@ impl Rule {
         );
 
         for (n, t) in new {
-            if base.contains_key(&n) { return Err(collision); }
-            else                     { base.insert(n, t);     }
+            match base.entry(n) {
+                Entry::Occupied(_) => return Err(collision),
+                Entry::Vacant(v)   => { v.insert(t); },
+            }
         }
 
         Ok(())
@@ -79,7 +81,7 @@ impl Rule {
     /// Note that this function takes the form unwrapped and in reverse -
     /// This is to make processing the bindings more efficient,
     /// As this function works with the head of the form.
-    pub fn bind(arg_pat: &Spanned<ArgPat>, mut reversed_form: &mut Vec<Spanned<AST>>)
+    pub fn bind(arg_pat: &Spanned<ArgPat>, reversed_form: &mut Vec<Spanned<AST>>)
     -> Option<Result<Bindings, Syntax>> {
         match &arg_pat.item {
             ArgPat::Keyword(expected) => {
@@ -96,7 +98,7 @@ impl Rule {
                 let mut bindings = HashMap::new();
                 for pat in pats {
                     let span = pat.span.clone();
-                    let new = match Rule::bind(&pat, &mut reversed_form)? {
+                    let new = match Rule::bind(pat, reversed_form)? {
                         Ok(matched) => matched,
                         mismatch @ Err(_) => return Some(mismatch),
                     };
@@ -113,8 +115,7 @@ impl Rule {
     /// of the format `#_<base>_XXXXXXXX`,
     /// Gauranteed not to exist in bindings.
     pub fn unique_identifier(base: String, bindings: &Bindings) -> String {
-        let mut tries = 0;
-        for _ in 0..1024 {
+        for tries in 0..1024 {
             let stamp = stamp(tries);
             // for example, `foo` may become `#_foo_d56aea12`
             // this should not be constructible as a symbol.
@@ -123,7 +124,6 @@ impl Rule {
                 // println!("{}", modified);
                 return modified;
             }
-            tries += 1;
         }
         panic!("Generated 1024 new unique identifiers for macro expansion, but all were already in use!");
     }
@@ -149,7 +149,7 @@ impl Rule {
             match pattern.item {
                 ASTPattern::Symbol(name) => Rule::resolve_symbol(name, pattern.span, bindings)
                     .map(ASTPattern::try_from).unwrap(),
-                ASTPattern::Data(_) => pattern,
+                ASTPattern::Data(_) | ASTPattern::Discard => pattern,
                 // treat name as symbol?
                 ASTPattern::Label(name, pattern) => {
                     let span = pattern.span.clone();
@@ -157,6 +157,44 @@ impl Rule {
                         ASTPattern::label(name, Rule::expand_pattern(*pattern, bindings)?), span,
                     )
                 },
+                ASTPattern::Tuple(items) => Spanned::new(
+                    ASTPattern::Tuple(
+                        items.into_iter()
+                            .map(|i| Rule::expand_pattern(i, bindings))
+                            .collect::<Result<Vec<_>, _>>()?
+                    ),
+                    pattern.span,
+                ),
+                ASTPattern::Record(fields) => Spanned::new(
+                    ASTPattern::Record(
+                        fields.into_iter()
+                            .map(|(f, p)| Ok((f, Rule::expand_pattern(p, bindings)?)))
+                            .collect::<Result<Vec<_>, _>>()?
+                    ),
+                    pattern.span,
+                ),
+                ASTPattern::List(items) => Spanned::new(
+                    ASTPattern::List(
+                        items.into_iter()
+                            .map(|i| Rule::expand_pattern(i, bindings))
+                            .collect::<Result<Vec<_>, _>>()?
+                    ),
+                    pattern.span,
+                ),
+                ASTPattern::Cons { head, tail } => Spanned::new(
+                    ASTPattern::Cons {
+                        head: Box::new(Rule::expand_pattern(*head, bindings)?),
+                        tail: Box::new(Rule::expand_pattern(*tail, bindings)?),
+                    },
+                    pattern.span,
+                ),
+                ASTPattern::Where { pattern: inner, guard } => Spanned::new(
+                    ASTPattern::where_(
+                        Rule::expand_pattern(*inner, bindings)?,
+                        Rule::expand(*guard, bindings)?,
+                    ),
+                    pattern.span,
+                ),
                 ASTPattern::Chain(_) => todo!(),
             }
         )
@@ -174,7 +212,7 @@ impl Rule {
     }
 
     /// Takes a macro's tree and a set of bindings and produces a new hygenic tree.
-    pub fn expand(tree: Spanned<AST>, mut bindings: &mut Bindings)
+    pub fn expand(tree: Spanned<AST>, bindings: &mut Bindings)
     -> Result<Spanned<AST>, Syntax> {
         // TODO: should macros evaluate arguments as thunks before insertions?
         // TODO: allow macros to reference external definitions
@@ -185,7 +223,7 @@ impl Rule {
             // and replaced with a random symbol that does not collide with any other bindings
             // so that the next time the symbol is located,
             // it's consistently replaced, hygenically.
-            AST::Symbol(name) => return Ok(Rule::resolve_symbol(name, tree.span.clone(), &mut bindings)),
+            AST::Symbol(name) => return Ok(Rule::resolve_symbol(name, tree.span.clone(), bindings)),
             AST::Data(_) => return Ok(tree),
 
             // Apply the transformation to each form
@@ -202,6 +240,69 @@ impl Rule {
                     .collect::<Result<Vec<_>, _>>()?
             ),
 
+            // Apply the transformation to each item in the tuple
+            AST::Tuple(items) => AST::Tuple(
+                items.into_iter()
+                    .map(|i| Rule::expand(i, bindings))
+                    .collect::<Result<Vec<_>, _>>()?
+            ),
+
+            // Apply the transformation to each field's value, but not its name
+            AST::Record(fields) => AST::Record(
+                fields.into_iter()
+                    .map(|(f, v)| Ok((f, Rule::expand(v, bindings)?)))
+                    .collect::<Result<Vec<_>, _>>()?
+            ),
+
+            AST::List(items) => AST::List(
+                items.into_iter()
+                    .map(|i| Rule::expand(i, bindings))
+                    .collect::<Result<Vec<_>, _>>()?
+            ),
+
+            AST::Cons { head, tail } => {
+                let h = Rule::expand(*head, bindings)?;
+                let t = Rule::expand(*tail, bindings)?;
+                AST::cons(h, t)
+            },
+
+            AST::BinOp { op, left, right } => {
+                let l = Rule::expand(*left, bindings)?;
+                let r = Rule::expand(*right, bindings)?;
+                AST::binop(op, l, r)
+            },
+
+            AST::Not(expression) => AST::Not(
+                Box::new(Rule::expand(*expression, bindings)?)
+            ),
+
+            AST::Neg(expression) => AST::Neg(
+                Box::new(Rule::expand(*expression, bindings)?)
+            ),
+
+            AST::Match { value, arms } => {
+                let v = Rule::expand(*value, bindings)?;
+                let mut expanded = vec![];
+                for (pattern, expression) in arms {
+                    expanded.push((
+                        Rule::expand_pattern(pattern, bindings)?,
+                        Rule::expand(expression, bindings)?,
+                    ));
+                }
+                AST::match_(v, expanded)
+            },
+
+            AST::If { condition, then, otherwise } => {
+                let c = Rule::expand(*condition, bindings)?;
+                let t = Rule::expand(*then,      bindings)?;
+                let o = Rule::expand(*otherwise, bindings)?;
+                AST::if_else(c, t, o)
+            },
+
+            AST::Index { expression, field } => AST::index(
+                Rule::expand(*expression, bindings)?, field,
+            ),
+
             // Appy the transformation to the left and right sides of the composition
             AST::Composition { argument, function } => {
                 let a = Rule::expand(*argument, bindings)?;
@@ -234,6 +335,18 @@ impl Rule {
             AST::Print(expression) => AST::Print(
                 Box::new(Rule::expand(*expression, bindings)?)
             ),
+            AST::Fiber(expression) => AST::Fiber(
+                Box::new(Rule::expand(*expression, bindings)?)
+            ),
+            AST::Yield(expression) => AST::Yield(
+                Box::new(Rule::expand(*expression, bindings)?)
+            ),
+            AST::Error(expression) => AST::Error(
+                Box::new(Rule::expand(*expression, bindings)?)
+            ),
+            AST::Try(expression) => AST::Try(
+                Box::new(Rule::expand(*expression, bindings)?)
+            ),
 
             // TODO: Should labels be bindable in macros?
             AST::Label(kind, expression) => AST::Label(
@@ -7,19 +7,32 @@ use crate::common::span::Span;
 pub struct Syntax {
     pub message: String,
     pub span:    Span,
+    /// Further errors found along with this one, each pointing at its own span.
+    pub others:  Vec<Syntax>,
 }
 
 impl Syntax {
     /// Creates a new static error.
     pub fn error(message: &str, span: &Span) -> Syntax {
-        Syntax { message: message.to_string(), span: span.clone() }
+        Syntax { message: message.to_string(), span: span.clone(), others: vec![] }
+    }
+
+    /// Combines a number of errors into one, so they can be reported at once.
+    /// Returns `None` if there are no errors.
+    pub fn all(errors: Vec<Syntax>) -> Option<Syntax> {
+        let mut errors = errors.into_iter();
+        let mut first  = errors.next()?;
+        first.others.extend(errors);
+        Some(first)
     }
 }
 
 impl fmt::Display for Syntax {
     fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if !self.span.is_empty() { fmt::Display::fmt(&self.span, f)? };
-        write!(f, "Syntax Error: {}", self.message)
+        write!(f, "Syntax Error: {}", self.message)?;
+        for other in self.others.iter() { write!(f, "\n\n{}", other)?; }
+        Ok(())
     }
 }
 
@@ -50,4 +63,19 @@ Syntax Error: Unexpected token '\"Hello, world!\"'\
         let result = format!("{}", error);
         assert_eq!(result, target);
     }
+
+    #[test]
+    fn others() {
+        let source = Rc::new(Source::source("a\nb"));
+        let error = Syntax::all(vec![
+            Syntax::error("First", &Span::new(&source, 0, 1)),
+            Syntax::error("Second", &Span::new(&source, 2, 1)),
+        ]).unwrap();
+
+        // each error is shown with its own span
+        let result = format!("{}", error);
+        assert!(result.contains(" 1 | a\n   | ^\n   |\nSyntax Error: First\n\n"), "{}", result);
+        assert!(result.ends_with(" 2 | b\n   | ^\n   |\nSyntax Error: Second"), "{}", result);
+        assert_eq!(Syntax::all(vec![]), None);
+    }
 }
@@ -1,5 +1,6 @@
 use std::fmt::Display;
 use crate::common::data::Data;
+use crate::compiler::operator::BinOp;
 
 /// These are the different tokens the lexer will output.
 /// `Token`s with data contain that data,
@@ -12,12 +13,28 @@ pub enum Token {
     CloseBracket,
     OpenParen,
     CloseParen,
+    OpenSquare,
+    CloseSquare,
     Sep,
+    Pair,
+    Colon,
+    Dot,
+    Cons,
+    Where,
 
     Syntax,
     Assign,
     Lambda,
     Compose,
+    BinOp(BinOp),
+    Not,
+    If,
+    Else,
+    Match,
+    Fiber,
+    Yield,
+    Error,
+    Try,
     Print,
     // pseudokeywords
     Keyword(String),
@@ -45,11 +62,26 @@ impl Display for Token {
             Token::CloseBracket => "a closing bracket",
             Token::OpenParen    => "an openening paren",
             Token::CloseParen   => "a closing paren",
+            Token::OpenSquare   => "an opening square bracket",
+            Token::CloseSquare  => "a closing square bracket",
             Token::Sep          => "a separator",
+            Token::Pair         => "a tuple",
+            Token::Colon        => "a colon",
+            Token::Dot          => "an index",
+            Token::Cons         => "a list tail",
+            Token::Where        => "a guard",
             Token::Syntax       => "a syntax definition",
             Token::Assign       => "an assignment",
             Token::Lambda       => "a lambda",
             Token::Compose      => "a composition",
+            Token::Not          => "a not",
+            Token::If           => "an if",
+            Token::Else         => "an else",
+            Token::Match        => "a match",
+            Token::Fiber        => "a fiber",
+            Token::Yield        => "a yield",
+            Token::Error        => "an error",
+            Token::Try          => "a try",
             Token::Unit         => "the Unit, '()'",
             Token::Print        => "a print keyword",
             Token::Symbol       => "a symbol",
@@ -59,6 +91,7 @@ impl Display for Token {
             Token::End          => "end of source",
             Token::Keyword(k) => { return write!(f, "the pseudokeyword '{}", k); },
             Token::Boolean(b) => { return write!(f, "the boolean {}",         b); },
+            Token::BinOp(o)   => { return write!(f, "the operator '{}'",      o); },
 
         };
         write!(f, "{}", message)
@@ -23,30 +23,68 @@
 //! > TODO: direct users to the installation location.
 //!
 //! ## Embedding Passerine in Rust
-//! > TODO: Clean up crate visibility, create `run` function.
+//! > TODO: Clean up crate visibility.
 //!
 //! Add passerine to your `Cargo.toml`:
 //! ```toml
 //! # make sure this is the latest version
-//! passerine = 0.7
+//! passerine = 0.8
 //! ```
 //! Then simply:
-//! ```ignore
-//! // DISCLAIMER: The `run` function used here has not been implemented yet,
-//! //             although the underlying interface is mostly stable.
+//! ```
+//! passerine::eval("print \"Hello from Passerine!\"").unwrap();
+//! ```
+//! `eval` compiles and runs a string of source code,
+//! returning the value the code evaluates to.
+//! To run a file, or some other `Source`, use `run`.
+//! Both of these return an `Error` if the code could not be compiled or run.
+//!
+//! ## Calling Rust from Passerine
+//! Rust functions can be registered in an `FFI`,
+//! and called from Passerine like any other function:
+//! ```
+//! # use passerine::common::{data::Data, source::Source, ffi::{FFI, FFIFunction}};
+//! # use passerine::vm::vm::VM;
+//! let mut ffi = FFI::new();
+//! ffi.add("double", FFIFunction::new(|data| match data {
+//!     Data::Real(n) => Ok(Data::Real(n * 2.0)),
+//!     _ => Err("Expected a number".to_string()),
+//! }));
+//!
+//! let closure = passerine::compile_with_ffi(Source::source("double 21.0"), ffi).unwrap();
+//! assert_eq!(VM::init().eval(closure), Ok(Data::Real(42.0)));
+//! ```
+//! Variables defined in the script shadow host functions of the same name.
+//! Values can be converted between Rust types and `Data`
+//! with the `IntoData` and `FromData` traits in `common::convert`;
+//! `FFIFunction::typed` uses them to wrap functions over plain Rust types.
+//! If a host function returns an `Err`, the message is raised as a runtime error.
+//!
+//! > NOTE: print statements are a temporary workaround.
+//! > They'll be replaced by a function by version 0.11, once the FFI is solidified
+//!
+//! Printed values are written to stdout by default;
+//! to capture, redirect, or suppress them, pass a writer to `VM::set_output`.
 //!
-//! use passerine;
+//! ## Precompiling scripts
+//! Compiled bytecode can be saved and loaded with `common::bytecode`,
+//! so scripts don't need to be recompiled every time they're run:
+//! ```
+//! # use passerine::common::{bytecode, closure::Closure, data::Data, source::Source};
+//! # use passerine::vm::vm::VM;
+//! let closure = passerine::compile(Source::source("1.0 + 2.0")).unwrap();
+//! let bytes = bytecode::write(&closure.lambda).unwrap();
 //!
-//! fn main() {
-//!     passerine::run("print \"Hello from Passerine!\"");
-//! }
+//! let lambda = bytecode::read(&bytes).unwrap();
+//! assert_eq!(VM::init().eval(Closure::wrap(lambda)), Ok(Data::Real(3.0)));
 //! ```
-//! > NOTE: print statements are not yet implemented.
-//! > They'll be implemented by version 0.11, once the FFI is solidified
+//! Bytecode that calls host functions must be loaded with `bytecode::read_with_ffi`.
+//! Loaded bytecode is checked by `common::verify` before it's returned,
+//! so malformed bytecode is rejected with an error rather than crashing the `VM`.
 //!
 //! ## Overview of the compilation process
 //! > NOTE: For a more detail, read through the documentation
-//! for any of the components mentioned.
+//! > for any of the components mentioned.
 //!
 //! Within the compiler pipeline, source code is represented as a `Source` object.
 //! A source is a reference to some code, with an associated path
@@ -62,6 +100,7 @@
 //! Compilation steps can raise `Err(Syntax)`,
 //! indicating that an error occured.
 //! `Syntax` is just a `Span` and a message,
+//! along with any other errors found at the same time,
 //! which can be pretty-printed.
 //!
 //! The first phase of compilation is lexing.
@@ -114,6 +153,175 @@
 //!
 //! The `VM` is just a simple light stack-based VM.
 
+// explicit `return`s and `vm::vm`-style module paths are used throughout
+#![allow(
+    clippy::needless_return,
+    clippy::module_inception,
+    clippy::self_named_constructors,
+    clippy::new_without_default,
+)]
+
 pub mod common;
 pub mod compiler;
 pub mod vm;
+
+use std::{
+    fmt,
+    rc::Rc,
+};
+
+use crate::common::{
+    source::Source,
+    closure::Closure,
+    data::Data,
+    ffi::FFI,
+};
+
+use crate::compiler::{
+    lex, parse, desugar,
+    gen::gen_with_ffi,
+    syntax::Syntax,
+};
+
+use crate::vm::{
+    vm::VM,
+    trace::Trace,
+};
+
+/// Represents an error raised at any point in the pipeline.
+/// A `Syntax` error is raised at compile time,
+/// whereas a `Trace` is raised while running.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    Syntax(Syntax),
+    Trace(Trace),
+}
+
+impl From<Syntax> for Error {
+    fn from(syntax: Syntax) -> Error { Error::Syntax(syntax) }
+}
+
+impl From<Trace> for Error {
+    fn from(trace: Trace) -> Error { Error::Trace(trace) }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Syntax(syntax) => fmt::Display::fmt(syntax, f),
+            Error::Trace(trace)   => fmt::Display::fmt(trace,  f),
+        }
+    }
+}
+
+/// Compiles a `Source` all the way down to a `Closure`,
+/// which can then be run by the `VM`.
+/// This runs each step of the compilation pipeline in turn.
+pub fn compile(source: Rc<Source>) -> Result<Closure, Syntax> {
+    compile_with_ffi(source, FFI::new())
+}
+
+/// Compiles a `Source` to a `Closure`, like `compile`,
+/// allowing the source to call the host functions registered in an `FFI`.
+pub fn compile_with_ffi(source: Rc<Source>, ffi: FFI) -> Result<Closure, Syntax> {
+    let lambda = lex(source)
+        .and_then(parse)
+        .and_then(desugar)
+        .and_then(|cst| gen_with_ffi(cst, ffi))?;
+
+    return Ok(Closure::wrap(lambda));
+}
+
+/// Compiles and runs a `Source` on a fresh `VM`,
+/// returning the value the source evaluates to.
+pub fn run(source: Rc<Source>) -> Result<Data, Error> {
+    let closure = compile(source)?;
+    let mut vm  = VM::init();
+    return Ok(vm.eval(closure)?);
+}
+
+/// Compiles and runs a string of Passerine code.
+/// This is a shortcut for calling `run` on a `Source::source`.
+pub fn eval(source: &str) -> Result<Data, Error> {
+    run(Source::source(source))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::ffi::FFIFunction;
+
+    #[test]
+    fn eval_value() {
+        let result = eval("x = true; y = x -> x; y x");
+        assert_eq!(result, Ok(Data::Boolean(true)));
+    }
+
+    #[test]
+    fn eval_syntax_error() {
+        match eval("x = )") {
+            Err(Error::Syntax(_)) => (),
+            other => panic!("Expected a syntax error, found {:?}", other),
+        }
+    }
+
+    fn host() -> FFI {
+        let mut ffi = FFI::new();
+        ffi.add("double", FFIFunction::new(|data| match data {
+            Data::Real(n) => Ok(Data::Real(n * 2.0)),
+            _ => Err("Expected a number".to_string()),
+        }));
+        ffi
+    }
+
+    #[test]
+    fn host_function() {
+        let source = Source::source("
+            twice = f x -> f (f x)
+            quad = x -> double (double x)
+            (double 1.0, twice double 2.0, quad 3.0)
+        ");
+        let closure = compile_with_ffi(source, host()).unwrap();
+
+        assert_eq!(
+            VM::init().eval(closure),
+            Ok(Data::Tuple(vec![Data::Real(2.0), Data::Real(8.0), Data::Real(12.0)])),
+        );
+    }
+
+    #[test]
+    fn host_function_shadowed() {
+        let source = Source::source("double = x -> x; double 1.0");
+        let closure = compile_with_ffi(source, host()).unwrap();
+        assert_eq!(VM::init().eval(closure), Ok(Data::Real(1.0)));
+    }
+
+    #[test]
+    fn host_function_error() {
+        let source = Source::source("double true");
+        let closure = compile_with_ffi(source, host()).unwrap();
+        let trace = VM::init().eval(closure).unwrap_err();
+        let message = format!("{}", trace);
+        assert!(message.contains("double true"));
+        assert!(message.contains("Runtime Host Error: Expected a number"));
+
+        // host errors can be caught like any other
+        let source = Source::source("try (double ())");
+        let closure = compile_with_ffi(source, host()).unwrap();
+        assert_eq!(
+            VM::init().eval(closure),
+            Ok(Data::Label(
+                Box::new("Result.Error".to_string()),
+                Box::new(Data::String("Expected a number".to_string())),
+            )),
+        );
+    }
+
+    #[test]
+    fn eval_runtime_error() {
+        match eval("x = true; x ()") {
+            Err(Error::Trace(_)) => (),
+            other => panic!("Expected a runtime error, found {:?}", other),
+        }
+    }
+}
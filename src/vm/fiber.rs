@@ -0,0 +1,90 @@
+use std::any::Any;
+
+use crate::common::{
+    lambda::Lambda,
+    closure::Closure,
+    fiber::{FiberRef, FiberState, Status},
+};
+
+use crate::vm::stack::Stack;
+
+/// Installed while trying an arm of a match.
+/// If a pattern fails to match,
+/// the stack is restored to its height when the handler was installed,
+/// and execution continues at the next arm.
+#[derive(Debug)]
+pub struct Handler {
+    pub height: usize,
+    pub ip:     usize,
+}
+
+/// The state of a function that called another function,
+/// restored once the called function returns.
+#[derive(Debug)]
+pub struct Caller {
+    pub closure:  Closure,
+    pub ip:       usize,
+    pub handlers: Vec<Handler>,
+}
+
+/// The state of a fiber that resumed another fiber,
+/// restored once the resumed fiber yields, returns, or crashes.
+#[derive(Debug)]
+pub struct Resumer {
+    pub fiber:   Fiber,
+    /// The fiber that was resumed,
+    /// or `None` if it was started by a `try`.
+    pub resumed: Option<FiberRef>,
+    /// The number of calls and fibers the resumed fiber is nested in,
+    /// counting those of every fiber that resumed it.
+    pub depth:   usize,
+}
+
+/// A fiber is a lightweight thread of execution,
+/// with its own stack, call frames, and instruction pointer.
+/// Fibers are cooperatively scheduled:
+/// a fiber runs until it yields a value back to the fiber that resumed it.
+/// The `VM` holds the state of the running fiber;
+/// the state of every other fiber is stored in a `Fiber`.
+#[derive(Debug)]
+pub struct Fiber {
+    pub closure:  Closure,
+    pub stack:    Stack,
+    pub ip:       usize,
+    pub handlers: Vec<Handler>,
+    pub callers:  Vec<Caller>,
+    pub status:   Status,
+}
+
+impl Fiber {
+    /// Creates a new suspended fiber that will call a closure when resumed.
+    /// The value the fiber is first resumed with is passed to the closure.
+    pub fn new(closure: Closure) -> Fiber {
+        // the fiber's stack is set up as if the closure has just been called,
+        // so the closure's argument is pushed when the fiber is resumed.
+        let mut stack = Stack::init();
+        stack.push_frame();
+
+        Fiber {
+            closure,
+            stack,
+            ip:       0,
+            handlers: vec![],
+            callers:  vec![],
+            status:   Status::Suspended,
+        }
+    }
+
+    /// Creates an empty fiber in a given state,
+    /// used to stand in for fibers that are running or done.
+    pub fn empty(status: Status) -> Fiber {
+        Fiber { status, ..Fiber::new(Closure::wrap(Lambda::empty())) }
+    }
+}
+
+
+impl FiberState for Fiber {
+    fn status(&self) -> Status { self.status }
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+}
@@ -0,0 +1,206 @@
+use std::{
+    mem,
+    rc::{Rc, Weak},
+    cell::RefCell,
+    collections::HashMap,
+};
+
+use crate::common::data::Data;
+
+/// Collect garbage once this many cells are tracked, at the least.
+const MIN_THRESHOLD: usize = 1024;
+
+/// Collects cycles of heaped variables.
+///
+/// Captured variables are moved to the heap, in `Rc<RefCell<Data>>` cells.
+/// A closure that captures the variable it is assigned to,
+/// like any recursive local function, forms a cycle:
+/// the cell holds the closure, which holds the cell.
+/// Reference counting alone will never free it.
+///
+/// The collector tracks every cell the `VM` creates.
+/// To find garbage, it counts how many references to each cell
+/// come from the contents of other tracked cells.
+/// A cell with more references than that is held from outside,
+/// i.e. from a stack, so it's alive,
+/// as is everything reachable from it.
+/// The remaining cells are only held by each other, so they're garbage:
+/// their contents are cleared, breaking the cycles.
+///
+/// Data that may be shared outside of a cell,
+/// like fibers or the shared tail of a list, is never looked into,
+/// so cycles through them are not collected.
+/// This errs on the side of keeping data alive.
+#[derive(Debug)]
+pub struct Collector {
+    cells:     Vec<Weak<RefCell<Data>>>,
+    /// The number of tracked cells at which to collect next.
+    threshold: usize,
+}
+
+impl Collector {
+    /// Creates a new collector with no tracked cells.
+    pub fn new() -> Collector {
+        Collector { cells: vec![], threshold: MIN_THRESHOLD }
+    }
+
+    /// The number of cells being tracked, some of which may have been freed.
+    pub fn tracked(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Starts tracking a new cell.
+    /// Returns true if enough cells have been created that it's time to collect.
+    pub fn track(&mut self, cell: &Rc<RefCell<Data>>) -> bool {
+        self.cells.push(Rc::downgrade(cell));
+        self.cells.len() >= self.threshold
+    }
+
+    /// Frees every cycle of cells that is unreachable from outside,
+    /// returning the number of cells that were garbage.
+    pub fn collect(&mut self) -> usize {
+        let cells = mem::take(&mut self.cells).iter()
+            .filter_map(Weak::upgrade)
+            .collect::<Vec<_>>();
+
+        let index = cells.iter().enumerate()
+            .map(|(i, cell)| (Rc::as_ptr(cell) as usize, i))
+            .collect::<HashMap<_, _>>();
+
+        // the tracked cells each cell refers to
+        let edges = cells.iter().map(|cell| {
+            let mut targets = vec![];
+            Collector::edges(&cell.borrow(), &mut |address| {
+                if let Some(&i) = index.get(&address) { targets.push(i); }
+            });
+            targets
+        }).collect::<Vec<_>>();
+
+        let mut internal = vec![0; cells.len()];
+        for target in edges.iter().flatten() { internal[*target] += 1; }
+
+        // one reference to each cell is held by `cells`
+        let mut reachable = cells.iter().enumerate()
+            .map(|(i, cell)| Rc::strong_count(cell) - 1 > internal[i])
+            .collect::<Vec<_>>();
+
+        let mut pending = (0..cells.len()).filter(|i| reachable[*i]).collect::<Vec<_>>();
+        while let Some(i) = pending.pop() {
+            for &target in edges[i].iter() {
+                if !reachable[target] {
+                    reachable[target] = true;
+                    pending.push(target);
+                }
+            }
+        }
+
+        // clear the garbage, dropping the contents only once every cycle is broken
+        let mut garbage = vec![];
+        for (i, cell) in cells.iter().enumerate() {
+            if reachable[i] {
+                self.cells.push(Rc::downgrade(cell));
+            } else {
+                garbage.push(cell.replace(Data::Unit));
+            }
+        }
+
+        let collected = garbage.len();
+        mem::drop(garbage);
+        mem::drop(cells);
+
+        self.threshold = MIN_THRESHOLD.max(self.cells.len() * 2);
+        return collected;
+    }
+
+    /// Calls a function with the address of each cell some data refers to.
+    fn edges(data: &Data, f: &mut impl FnMut(usize)) {
+        match data {
+            Data::Heaped(cell) => f(Rc::as_ptr(cell) as usize),
+            Data::Closure(closure) => for cell in closure.captures.iter() {
+                f(Rc::as_ptr(cell) as usize);
+            },
+            Data::Label(_, data) => Collector::edges(data, f),
+            Data::Tuple(items) => for item in items.iter() { Collector::edges(item, f); },
+            Data::Record(fields) => for (_, value) in fields.iter() { Collector::edges(value, f); },
+            Data::List(list) => for item in list.owned() { Collector::edges(item, f); },
+            // fibers may be held from outside, so they're not looked into
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::{
+        closure::Closure,
+        lambda::Lambda,
+        list::List,
+    };
+
+    /// Creates a cell holding a closure that captures the cell.
+    fn cycle(collector: &mut Collector) -> Rc<RefCell<Data>> {
+        let cell = Rc::new(RefCell::new(Data::Unit));
+        let mut closure = Closure::wrap(Lambda::empty());
+        closure.captures.push(cell.clone());
+        cell.replace(Data::Closure(Box::new(closure)));
+        collector.track(&cell);
+        cell
+    }
+
+    /// Gives up a strong reference to a cell.
+    fn weak(cell: Rc<RefCell<Data>>) -> Weak<RefCell<Data>> {
+        Rc::downgrade(&cell)
+    }
+
+    #[test]
+    fn collects_cycles() {
+        let mut collector = Collector::new();
+        let garbage = weak(cycle(&mut collector));
+        let alive   = cycle(&mut collector);
+
+        assert_eq!(collector.collect(), 1);
+        assert!(garbage.upgrade().is_none());
+        assert!(matches!(&*alive.borrow(), Data::Closure(_)));
+        assert_eq!(collector.tracked(), 1);
+    }
+
+    #[test]
+    fn keeps_reachable() {
+        let mut collector = Collector::new();
+        let first  = cycle(&mut collector);
+        let second = cycle(&mut collector);
+
+        // the second cell is only reachable through the first
+        let closure = first.replace(Data::Unit);
+        first.replace(Data::Tuple(vec![closure, Data::Heaped(second.clone())]));
+        let second = weak(second);
+
+        assert_eq!(collector.collect(), 0);
+        assert!(second.upgrade().is_some());
+
+        mem::drop(first);
+        assert_eq!(collector.collect(), 2);
+        assert!(second.upgrade().is_none());
+    }
+
+    #[test]
+    fn shared_lists() {
+        let mut collector = Collector::new();
+        let first  = cycle(&mut collector);
+        let second = cycle(&mut collector);
+
+        // the list holding the second cell is also held from outside
+        let list = List::empty().prepend(Data::Heaped(second));
+        let closure = first.replace(Data::Unit);
+        first.replace(Data::Tuple(vec![closure, Data::List(list.clone())]));
+        let first = weak(first);
+
+        assert_eq!(collector.collect(), 1);
+        assert!(first.upgrade().is_none());
+        assert_eq!(collector.tracked(), 1);
+
+        mem::drop(list);
+        assert_eq!(collector.collect(), 1);
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+/// A handle that can stop a running `VM`, possibly from another thread.
+/// Handles are cheap to clone, and every clone interrupts the same `VM`.
+/// The `VM` checks for interrupts before each instruction,
+/// stopping with a fatal `Interrupted` error.
+/// Once the `VM` has stopped, the interrupt is cleared,
+/// so the `VM` can be used again.
+/// Interrupting a `VM` that isn't running stops its next run
+/// before it runs any instructions, so an interrupt is never lost,
+/// even if it's made just as a run starts.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    /// Creates a new handle that has not been interrupted.
+    pub fn new() -> InterruptHandle {
+        InterruptHandle(Arc::new(AtomicBool::new(false)))
+    }
+
+    /// Asks the `VM` to stop.
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the `VM` has been asked to stop, but hasn't yet.
+    pub fn is_interrupted(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Clears the interrupt, returning whether there was one.
+    pub fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
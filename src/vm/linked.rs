@@ -1,5 +1,3 @@
-use std::mem;
-
 /// A linked list of `usize`s that functions as a stack.
 /// Used to keep track of the current stack frame while preserving
 /// the indicies of past frames.
@@ -15,7 +13,7 @@ impl Linked {
 
     /// Add a new entry to the top of the linked stack.
     pub fn prepend(&mut self, new_index: usize) {
-        let old_tail = mem::replace(&mut self.1, None);
+        let old_tail = self.1.take();
         let old = Linked(self.0, old_tail);
         *self = Linked(new_index, Some(Box::new(old)));
     }
@@ -23,7 +21,7 @@ impl Linked {
     /// Remove the top entry of the linked stack, returning the top value.
     pub fn prepop(&mut self) -> usize {
         let index = self.0;
-        *self = *mem::replace(&mut self.1, None)
+        *self = *self.1.take()
             .expect("Can not pop back past root link");
         return index;
     }
@@ -0,0 +1,163 @@
+use std::{
+    mem,
+    rc::Rc,
+    collections::HashSet,
+};
+
+use crate::common::{
+    data::Data,
+    list::List,
+    lambda::Lambda,
+    closure::Closure,
+};
+
+use crate::vm::{
+    stack::Stack,
+    tag::Tagged,
+    fiber::Fiber,
+};
+
+/// Estimates the number of bytes held by data in the VM.
+/// Data shared through reference counting, like heaped variables,
+/// list tails, and fibers, is only counted once.
+/// The estimate is approximate: it counts the data itself,
+/// not the overhead of the allocator.
+#[derive(Debug, Default)]
+pub struct Memory {
+    /// The addresses of the shared data that's already been counted.
+    seen:  HashSet<usize>,
+    total: usize,
+}
+
+impl Memory {
+    /// Creates a new empty estimate.
+    pub fn new() -> Memory {
+        Memory { seen: HashSet::new(), total: 0 }
+    }
+
+    /// The number of bytes counted so far.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Returns true the first time shared data at an address is seen.
+    fn first(&mut self, address: usize) -> bool {
+        self.seen.insert(address)
+    }
+
+    /// Counts everything on a stack.
+    pub fn stack(&mut self, stack: &Stack) {
+        self.total += stack.stack.capacity() * mem::size_of::<Tagged>();
+        for tagged in stack.stack.iter() {
+            tagged.inspect(|data| {
+                // small data is stored inline, everything else is boxed
+                if let Data::Real(_) | Data::Unit | Data::Boolean(_) | Data::Frame | Data::NotInit = data {
+                    return;
+                }
+                self.total += mem::size_of::<Data>();
+                self.inner(data);
+            });
+        }
+    }
+
+    /// Counts the state of a suspended fiber.
+    pub fn fiber(&mut self, fiber: &Fiber) {
+        self.stack(&fiber.stack);
+        self.closure(&fiber.closure);
+        for caller in fiber.callers.iter() { self.closure(&caller.closure); }
+    }
+
+    /// Counts a closure, including the lambda and the variables it captures.
+    pub fn closure(&mut self, closure: &Closure) {
+        self.total += mem::size_of::<Closure>() + closure.id.len();
+        self.lambda(&closure.lambda);
+
+        for captured in closure.captures.iter() {
+            if self.first(Rc::as_ptr(captured) as usize) {
+                self.total += mem::size_of::<Data>();
+                self.inner(&captured.borrow());
+            }
+        }
+    }
+
+    /// Counts the bytecode and constants of a lambda.
+    pub fn lambda(&mut self, lambda: &Lambda) {
+        self.total += lambda.code.len()
+            + lambda.spans.len() * mem::size_of::<(usize, usize)>();
+
+        for constant in lambda.constants.iter() { self.data(constant); }
+    }
+
+    /// Counts some data.
+    pub fn data(&mut self, data: &Data) {
+        self.total += mem::size_of::<Data>();
+        self.inner(data);
+    }
+
+    /// Counts the data some data points to, but not the data itself.
+    fn inner(&mut self, data: &Data) {
+        match data {
+            Data::Heaped(cell) => if self.first(Rc::as_ptr(cell) as usize) {
+                self.data(&cell.borrow());
+            },
+            Data::String(s) | Data::Kind(s) => self.total += s.len(),
+            Data::Lambda(lambda) => self.lambda(lambda),
+            Data::Closure(closure) => self.closure(closure),
+            Data::Fiber(fiber) => if self.first(fiber.address()) {
+                // a running fiber is stored in the VM, not the reference
+                if let Some(fiber) = fiber.try_borrow::<Fiber>() { self.fiber(&fiber); }
+            },
+            Data::Label(kind, data) => {
+                self.total += kind.len();
+                self.data(data);
+            },
+            Data::Tuple(items) => for item in items.iter() { self.data(item); },
+            Data::Record(fields) => for (name, value) in fields.iter() {
+                self.total += name.len() + mem::size_of::<String>();
+                self.data(value);
+            },
+            Data::List(list) => for (address, item) in list.nodes() {
+                // once a shared tail is reached, the rest has been counted
+                if !self.first(address) { break; }
+                self.total += List::NODE_SIZE;
+                self.inner(item);
+            },
+            Data::Real(_)
+            | Data::Boolean(_)
+            | Data::Unit
+            | Data::Frame
+            | Data::NotInit
+            | Data::Host(_) => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn strings() {
+        let mut short = Memory::new();
+        short.data(&Data::String("a".to_string()));
+        let mut long = Memory::new();
+        long.data(&Data::String("a".repeat(1000)));
+        assert_eq!(long.total() - short.total(), 999);
+    }
+
+    #[test]
+    fn shared_lists() {
+        let tail = (0..100).map(|n| Data::Real(n as f64)).collect::<List>();
+        let mut once = Memory::new();
+        once.data(&Data::List(tail.clone()));
+
+        // the tail is shared, so it's only counted once
+        let mut twice = Memory::new();
+        twice.data(&Data::Tuple(vec![
+            Data::List(tail.prepend(Data::Unit)),
+            Data::List(tail),
+        ]));
+
+        assert!(twice.total() < once.total() + 4 * List::NODE_SIZE);
+    }
+}
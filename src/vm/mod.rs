@@ -3,6 +3,10 @@
 //! But should never be used outside of the module by `common` or `compiler`.
 
 pub mod vm;
+pub mod fiber;
+pub mod memory;
+pub mod interrupt;
+pub mod gc;
 
 pub mod tag;
 pub mod linked;
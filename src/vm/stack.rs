@@ -60,6 +60,13 @@ impl Stack {
         }
     }
 
+    /// Drops everything on the `Stack` above a certain height.
+    /// Note that this should not be used to pop frames.
+    #[inline]
+    pub fn truncate(&mut self, height: usize) {
+        self.stack.truncate(height);
+    }
+
     /// Pops a stack frame from the `Stack`, restoring the previous frame.
     /// Panics if there are no frames left on the stack.
     #[inline]
@@ -73,6 +80,14 @@ impl Stack {
         }
     }
 
+    /// Drops a stack frame and everything above it,
+    /// used to clean up after an error.
+    #[inline]
+    pub fn drop_frame(&mut self) {
+        let index = self.frames.prepop();
+        self.stack.truncate(index);
+    }
+
     /// Pushes a new stack frame onto the `Stack`.
     #[inline]
     pub fn push_frame(&mut self) {
@@ -81,14 +96,16 @@ impl Stack {
     }
 
     /// Wraps the top data value on the stack in `Data::Heaped`,
-    /// data must not already be on the heap
+    /// data must not already be on the heap.
+    /// Returns the new heap cell.
     #[inline]
-    pub fn heapify(&mut self, index: usize) {
+    pub fn heapify(&mut self, index: usize) -> Rc<RefCell<Data>> {
         let local_index = self.frames.peek() + index + 1;
 
         let data = mem::replace(&mut self.stack[local_index], Tagged::frame()).data();
-        let heaped = Data::Heaped(Rc::new(RefCell::new(data)));
-        mem::drop(mem::replace(&mut self.stack[local_index], Tagged::new(heaped)));
+        let cell = Rc::new(RefCell::new(data));
+        mem::drop(mem::replace(&mut self.stack[local_index], Tagged::new(Data::Heaped(cell.clone()))));
+        cell
     }
 
     pub fn local_data(&mut self, index: usize) -> Data {
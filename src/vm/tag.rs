@@ -50,7 +50,8 @@ impl Tagged {
     /// Wraps `Data` to create a new tagged pointer.
     pub fn new(data: Data) -> Tagged {
         match data {
-            // Real
+            // Real, NaNs carrying a payload could be mistaken for other data
+            Data::Real(f) if f.is_nan() => Tagged(f64::NAN.to_bits()),
             Data::Real(f) => Tagged(f.to_bits()),
             // Unit
             Data::Unit => Tagged(QNAN | U_FLAG),
@@ -118,11 +119,22 @@ impl Tagged {
         };
 
         // println!("-- Forgetting...");
-        mem::drop(self.0);
         mem::forget(self);
         return d;
     }
 
+    /// Calls a function with a reference to the tagged data,
+    /// without copying it.
+    pub fn inspect<T>(&self, f: impl FnOnce(&Data) -> T) -> T {
+        unsafe {
+            match self.extract() {
+                Ok(data) => f(&data),
+                // we do not own the pointer, so it must not be dropped
+                Err(boxed) => f(&mem::ManuallyDrop::new(boxed)),
+            }
+        }
+    }
+
     /// Deeply copies some `Tagged` data.
     pub fn copy(&self) -> Data {
         // println!("-- Copy...");
@@ -199,6 +211,16 @@ mod test {
         }
     }
 
+    #[test]
+    fn nan_payloads() {
+        // the bits of a tagged pointer
+        let nan = f64::from_bits(P_FLAG | QNAN | 0xDEAD_BEE8);
+        match Tagged::new(Data::Real(nan)).data() {
+            Data::Real(f) => assert!(f.is_nan()),
+            _             => panic!("Didn't unwrap to a real"),
+        }
+    }
+
     #[test]
     fn bool_and_back() {
         assert_eq!(Data::Boolean(true),  Tagged::new(Data::Boolean(true) ).data());
@@ -1,11 +1,19 @@
 use std::fmt;
-use crate::common::span::Span;
+use crate::common::{
+    span::Span,
+    data::Data,
+};
 
 /// Represents a runtime error, i.e. a traceback
 #[derive(Debug, PartialEq, Eq)]
 pub struct Trace {
     kind: String, // TODO: enum?
     message: String,
+    /// The data raised by `error`, if any.
+    data: Option<Data>,
+    /// Fatal errors are raised by the host's limits, not the script,
+    /// so they can not be caught by a `try`.
+    fatal: bool,
     spans: Vec<Span>,
 }
 
@@ -15,10 +23,48 @@ impl Trace {
         Trace {
             kind: kind.to_string(),
             message: message.to_string(),
+            data: None,
+            fatal: false,
             spans,
         }
     }
 
+    /// Creates a new traceback for a fatal error,
+    /// which unwinds through every `try` and stops the VM.
+    pub fn fatal(kind: &str, message: &str, spans: Vec<Span>) -> Trace {
+        Trace { fatal: true, ..Trace::error(kind, message, spans) }
+    }
+
+    /// Creates a new traceback for some data raised with `error`.
+    pub fn raise(data: Data, spans: Vec<Span>) -> Trace {
+        Trace {
+            kind: "Uncaught".to_string(),
+            message: data.describe(),
+            data: Some(data),
+            fatal: false,
+            spans,
+        }
+    }
+
+    /// The kind of error, i.e. `"Type"` or `"Fuel"`.
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    /// Whether the error is fatal, see `Trace::fatal`.
+    pub fn is_fatal(&self) -> bool {
+        self.fatal
+    }
+
+    /// The value of the error when caught by a `try`:
+    /// either the data that was raised, or the error message.
+    pub fn data(self) -> Data {
+        match self.data {
+            Some(data) => data,
+            None => Data::String(self.message),
+        }
+    }
+
     /// Used to add context (i.e. function calls) while unwinding the stack.
     pub fn add_context(&mut self, span: Span) {
         self.spans.push(span);
@@ -92,4 +138,14 @@ forever RandomLabel
         let result = format!("{}", traceback);
         assert_eq!(result, target);
     }
+
+    #[test]
+    fn raise_undisplayable() {
+        // data the VM uses internally is described, rather than displayed
+        let trace = Trace::raise(Data::Tuple(vec![Data::Real(1.0), Data::Kind("Some".to_string())]), vec![]);
+        assert_eq!(trace.message, "Tuple([Real(1.0), Kind(Some)])");
+
+        let trace = Trace::raise(Data::Real(1.0), vec![]);
+        assert_eq!(trace.message, "1");
+    }
 }
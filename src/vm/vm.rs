@@ -1,17 +1,30 @@
-use std::mem;
+use std::{
+    fmt,
+    mem,
+    io::{self, Write},
+};
 
 use crate::common::{
     number::build_number,
     data::Data,
+    list::List,
     opcode::Opcode,
     lambda::{Captured, Lambda},
     closure::Closure,
+    ffi::FFIFunction,
+    fiber::{FiberRef, Status},
 };
 
+use crate::compiler::operator::BinOp;
+
 use crate::vm::{
     trace::Trace,
     // tag::Tagged,
     stack::Stack,
+    memory::Memory,
+    interrupt::InterruptHandle,
+    gc::Collector,
+    fiber::{Fiber, Handler, Caller, Resumer},
 };
 
 /// A `VM` executes bytecode lambda closures.
@@ -19,11 +32,62 @@ use crate::vm::{
 /// VM initialization overhead is tiny,
 /// and each VM's state is self-contained,
 /// so more than one can be spawned if needed.
+/// The VM holds the state of the running fiber;
+/// when another fiber is resumed, the states are swapped.
 #[derive(Debug)]
 pub struct VM {
-    closure: Closure,
-    stack:   Stack,
-    ip:      usize,
+    closure:  Closure,
+    stack:    Stack,
+    ip:       usize,
+    /// Handlers for pattern mismatches in the current closure,
+    /// most recently installed last.
+    handlers: Vec<Handler>,
+    /// The functions that called the current closure,
+    /// most recent caller last.
+    callers:  Vec<Caller>,
+    /// The fibers that resumed the running fiber, most recent last.
+    resumers: Vec<Resumer>,
+    /// Where `print` writes to, stdout by default.
+    output:   Output,
+    /// The maximum number of nested calls and fibers, see `depth`.
+    limit:    usize,
+    /// The number of instructions left to run, if limited.
+    fuel:     Option<usize>,
+    /// The number of instructions run so far.
+    consumed: usize,
+    /// The maximum number of bytes the VM may hold, if limited.
+    memory_limit: Option<usize>,
+    /// An overestimate of the number of bytes the VM holds,
+    /// see `allocate`.
+    estimate: usize,
+    /// Once the estimate exceeds this, the VM is measured again.
+    remeasure: usize,
+    /// Set from outside the VM to stop it.
+    interrupt: InterruptHandle,
+    /// Frees cycles of heaped variables, see `collect_garbage`.
+    gc: Collector,
+}
+
+/// The default recursion limit.
+/// Calls don't use the Rust stack, so this can be fairly high;
+/// it's there to catch runaway recursion before it eats all the memory.
+pub const RECURSION_LIMIT: usize = 100_000;
+
+/// The number of bytes each instruction is assumed to allocate,
+/// when accounting for memory use.
+const STEP_SIZE: usize = 2 * mem::size_of::<Data>();
+
+/// Once measured, the VM is not measured again
+/// until its estimated memory grows by `1 / REMEASURE_FRACTION` of the memory limit.
+const REMEASURE_FRACTION: usize = 8;
+
+/// A writer that printed values are written to.
+struct Output(Box<dyn Write>);
+
+impl fmt::Debug for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Output")
+    }
 }
 
 // NOTE: use Opcode::same and Opcode.to_byte() rather than actual bytes
@@ -37,8 +101,181 @@ impl VM {
     pub fn init() -> VM {
         VM {
             closure: Closure::wrap(Lambda::empty()),
-            stack: Stack::init(),
-            ip:    0,
+            stack:    Stack::init(),
+            ip:       0,
+            handlers: vec![],
+            callers:  vec![],
+            resumers: vec![],
+            output:   Output(Box::new(io::stdout())),
+            limit:    RECURSION_LIMIT,
+            fuel:     None,
+            consumed: 0,
+            memory_limit: None,
+            estimate:     0,
+            remeasure:    0,
+            interrupt:    InterruptHandle::new(),
+            gc:           Collector::new(),
+        }
+    }
+
+    /// Returns a handle that can be used to stop the VM while it's running,
+    /// i.e. from another thread to enforce a timeout.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Raises an error if the VM has been interrupted,
+    /// clearing the interrupt.
+    fn check_interrupt(&mut self) -> Result<(), Trace> {
+        // this runs before every instruction, and reading the flag
+        // is cheaper than clearing it, so it's only cleared once set
+        if !self.interrupt.is_interrupted() || !self.interrupt.take() { return Ok(()); }
+
+        Err(Trace::fatal(
+            "Interrupted",
+            "The VM was interrupted",
+            vec![self.closure.lambda.index_span(self.ip)],
+        ))
+    }
+
+    /// Frees heaped variables that are only reachable from one another,
+    /// returning the number of variables freed.
+    /// Recursive local functions capture themselves,
+    /// so they can't be freed by reference counting alone.
+    /// This is done automatically as variables are captured,
+    /// but can be called between runs to free memory eagerly.
+    pub fn collect_garbage(&mut self) -> usize {
+        self.gc.collect()
+    }
+
+    /// Limits the approximate number of bytes of data the VM may hold,
+    /// or removes the limit if `None`.
+    /// Exceeding the limit raises a fatal `Memory` error.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory_limit = limit;
+    }
+
+    /// Estimates the number of bytes of data the VM holds,
+    /// including the stack, closures, heaped variables, and suspended fibers.
+    pub fn memory(&self) -> usize {
+        let mut memory = Memory::new();
+        memory.stack(&self.stack);
+        memory.closure(&self.closure);
+        for caller in self.callers.iter() { memory.closure(&caller.closure); }
+        for resumer in self.resumers.iter() { memory.fiber(&resumer.fiber); }
+        memory.total()
+    }
+
+    /// Accounts for data about to be allocated,
+    /// raising an error if it would exceed the memory limit.
+    /// Rather than measuring the VM after every allocation,
+    /// the size of each allocation is added to a running estimate;
+    /// the VM is only measured when the estimate exceeds the limit,
+    /// and has grown by a fraction of the limit since it was last measured.
+    /// This keeps a VM that holds nearly as much as the limit
+    /// from being measured on every allocation,
+    /// at the cost of briefly exceeding the limit by up to that fraction.
+    fn allocate(&mut self, bytes: usize) -> Result<(), Trace> {
+        let limit = match self.memory_limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+
+        self.estimate = self.estimate.saturating_add(bytes);
+        if self.estimate <= self.remeasure { return Ok(()); }
+
+        self.measure(limit, bytes);
+        if self.estimate <= limit { return Ok(()); }
+
+        Err(Trace::fatal(
+            "Memory",
+            &format!("The memory limit of {} bytes was exceeded", limit),
+            vec![self.closure.lambda.index_span(self.ip)],
+        ))
+    }
+
+    /// Measures the VM, plus some bytes about to be allocated,
+    /// resetting the estimate used by `allocate`.
+    fn measure(&mut self, limit: usize, bytes: usize) {
+        self.estimate  = self.memory().saturating_add(bytes);
+        self.remeasure = limit.max(self.estimate.saturating_add(limit / REMEASURE_FRACTION));
+    }
+
+    /// Limits the number of instructions the VM may run,
+    /// or removes the limit if `None`.
+    /// Once the fuel runs out, a fatal `Fuel` error is raised;
+    /// the VM can be refueled and used to run more code afterwards.
+    pub fn set_fuel(&mut self, fuel: Option<usize>) {
+        self.fuel = fuel;
+    }
+
+    /// The number of instructions the VM may still run, if limited.
+    pub fn fuel(&self) -> Option<usize> {
+        self.fuel
+    }
+
+    /// The total number of instructions the VM has run.
+    pub fn fuel_consumed(&self) -> usize {
+        self.consumed
+    }
+
+    /// Uses up a unit of fuel before running an instruction,
+    /// raising an error if there is none left.
+    fn burn(&mut self) -> Result<(), Trace> {
+        match self.fuel {
+            Some(0) => return Err(Trace::fatal(
+                "Fuel",
+                "The VM ran out of fuel",
+                vec![self.closure.lambda.index_span(self.ip)],
+            )),
+            Some(ref mut fuel) => *fuel -= 1,
+            None => (),
+        }
+
+        self.consumed += 1;
+        Ok(())
+    }
+
+    /// Sets the maximum number of nested calls and fibers,
+    /// counted across every fiber that resumed the running one.
+    /// Exceeding the limit raises an error.
+    pub fn set_recursion_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
+    /// The number of calls and fibers the running closure is nested in,
+    /// counting the callers of every fiber that resumed the running one.
+    fn depth(&self) -> usize {
+        self.callers.len() + self.resumers.last().map_or(0, |r| r.depth)
+    }
+
+    /// Raises an error if another call or fiber would exceed the recursion limit.
+    fn check_depth(&mut self, depth: usize) -> Result<(), Trace> {
+        if depth < self.limit { return Ok(()); }
+
+        Err(Trace::error(
+            "Recursion",
+            &format!("The recursion limit of {} was exceeded", self.limit),
+            vec![self.closure.lambda.index_span(self.ip)],
+        ))
+    }
+
+    /// Redirects the output of `print` to a writer.
+    /// To suppress output, use `std::io::sink()`.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Output(Box::new(output));
+    }
+
+    /// Replaces the state of the running fiber with that of another,
+    /// returning the state of the fiber that was running.
+    fn swap(&mut self, fiber: Fiber) -> Fiber {
+        Fiber {
+            closure:  mem::replace(&mut self.closure,  fiber.closure),
+            stack:    mem::replace(&mut self.stack,    fiber.stack),
+            ip:       mem::replace(&mut self.ip,       fiber.ip),
+            handlers: mem::replace(&mut self.handlers, fiber.handlers),
+            callers:  mem::replace(&mut self.callers,  fiber.callers),
+            status:   Status::Suspended,
         }
     }
 
@@ -87,28 +324,80 @@ impl VM {
             Opcode::Label   => self.label(),
             Opcode::UnLabel => self.un_label(),
             Opcode::UnData  => self.un_data(),
+            Opcode::Reserve => self.reserve(),
+            Opcode::Tuple   => self.tuple(),
+            Opcode::UnTuple => self.un_tuple(),
+            Opcode::Record  => self.record(),
+            Opcode::UnRecord => self.un_record(),
+            Opcode::Index   => self.index(),
+            Opcode::List    => self.list(),
+            Opcode::Cons    => self.cons(),
+            Opcode::UnList  => self.un_list(),
+            Opcode::UnCons  => self.un_cons(),
+
+              Opcode::Add
+            | Opcode::Sub
+            | Opcode::Mul
+            | Opcode::Div
+            | Opcode::Rem
+            | Opcode::Equal
+            | Opcode::NotEqual
+            | Opcode::Less
+            | Opcode::LessEqual
+            | Opcode::Greater
+            | Opcode::GreaterEqual
+            | Opcode::And
+            | Opcode::Or => self.binop(opcode),
+            Opcode::Not => self.not(),
+            Opcode::Neg => self.neg(),
+            Opcode::Jump      => self.jump(),
+            Opcode::JumpBack  => self.jump_back(),
+            Opcode::JumpFalse => self.jump_false(),
+            Opcode::Handle    => self.handle(),
+            Opcode::Unhandle  => self.unhandle(),
+            Opcode::NoMatch   => self.no_match(),
+            Opcode::Fail      => self.mismatch("The guard of the pattern is false"),
+            Opcode::Fiber     => self.fiber(),
+            Opcode::Yield     => self.yield_val(),
+            Opcode::Raise     => self.raise(),
+            Opcode::Try       => self.try_(),
+            Opcode::TailCall  => self.tail_call(),
         }
     }
 
     /// Suspends the current lambda and runs a new one on the VM.
     /// Runs until either success, in which it restores the state of the previous lambda,
     /// Or failure, in which it returns the runtime error.
-    /// In the future, fibers will allow for error handling -
-    /// right now, error in Passerine are practically panics.
+    /// Function calls and fibers are run in the same loop,
+    /// rather than recursively, so the state of a fiber can be suspended.
+    /// Errors crash the running fiber, and are caught by the nearest `try`;
+    /// if there is none, the stack is cleaned up and the error is returned.
     pub fn run(&mut self, closure: Closure) -> Result<(), Trace> {
         // cache current state, load new bytecode
-        let old_closure = mem::replace(&mut self.closure, closure);
-        let old_ip      = mem::replace(&mut self.ip,    0);
-        // TODO: should lambdas store their own ip?
+        let old_closure  = mem::replace(&mut self.closure, closure);
+        let old_ip       = mem::replace(&mut self.ip,    0);
+        // handlers only catch mismatches in the closure that installed them
+        let old_handlers = mem::take(&mut self.handlers);
+        let old_callers  = mem::take(&mut self.callers);
+        let old_resumers = mem::take(&mut self.resumers);
+        let height       = self.stack.stack.len();
+        // data already held by the VM counts towards the limit
+        if let Some(limit) = self.memory_limit { self.measure(limit, 0); }
 
         let mut result = Ok(());
 
         while self.ip < self.closure.lambda.code.len() {
             // println!("before: {:?}", self.stack.stack);
             // println!("executing: {:?}", Opcode::from_byte(self.peek_byte()));
-            if let error @ Err(_) = self.step() {
-                // TODO: clean up stack on error
-                result = error;
+            // each instruction allocates at most a little data,
+            // unless it says otherwise
+            let stepped = self.check_interrupt()
+                .and_then(|()| self.burn())
+                .and_then(|()| self.allocate(STEP_SIZE))
+                .and_then(|()| self.step());
+            if let Err(trace) = stepped.or_else(|trace| self.unwind(trace)) {
+                self.stack.truncate(height);
+                result = Err(trace);
                 // println!("Error!");
                 break;
             };
@@ -119,12 +408,56 @@ impl VM {
 
         // return current state
         mem::drop(mem::replace(&mut self.closure, old_closure));
-        self.ip = old_ip;
+        self.ip       = old_ip;
+        self.handlers = old_handlers;
+        self.callers  = old_callers;
+        self.resumers = old_resumers;
 
         // If something went wrong, the error will be returned.
         return result;
     }
 
+    /// Unwinds the call stack after an error,
+    /// adding the location of each call to the traceback along the way.
+    /// A fiber that crashes is done,
+    /// and the error continues to unwind through the fiber that resumed it,
+    /// until it reaches a `try`, which catches the error.
+    /// If the error is not caught, or is fatal, it is returned.
+    fn unwind(&mut self, mut trace: Trace) -> Result<(), Trace> {
+        loop {
+            while let Some(caller) = self.callers.pop() {
+                self.stack.drop_frame();
+                self.closure  = caller.closure;
+                self.ip       = caller.ip;
+                self.handlers = caller.handlers;
+                trace.add_context(self.closure.lambda.index_span(self.ip));
+            }
+
+            let resumer = match self.resumers.pop() {
+                Some(resumer) => resumer,
+                None => return Err(trace),
+            };
+
+            mem::drop(self.swap(resumer.fiber));
+            match resumer.resumed {
+                Some(fiber) => *fiber.borrow_mut::<Fiber>() = Fiber::empty(Status::Done),
+                None if trace.is_fatal() => (),
+                None => {
+                    self.stack.push_data(VM::result("Error", trace.data()));
+                    return self.done();
+                },
+            }
+            trace.add_context(self.closure.lambda.index_span(self.ip));
+        }
+    }
+
+    /// Runs a closure to completion,
+    /// returning the value it evaluated to.
+    pub fn eval(&mut self, closure: Closure) -> Result<Data, Trace> {
+        self.run(closure)?;
+        return Ok(self.stack.pop_data());
+    }
+
     // TODO: there are a lot of optimizations that can be made
     // I'll list a few here:
     // - searching the stack for variables
@@ -139,7 +472,9 @@ impl VM {
         // get the constant index
         let index = self.next_number();
 
-        self.stack.push_data(self.closure.lambda.constants[index].clone());
+        let constant = self.closure.lambda.constants[index].clone();
+        if let Data::String(s) = &constant { self.allocate(s.len())?; }
+        self.stack.push_data(constant);
         self.done()
     }
 
@@ -148,7 +483,8 @@ impl VM {
     #[inline]
     pub fn capture(&mut self) -> Result<(), Trace> {
         let index = self.next_number();
-        self.stack.heapify(index);   // move value to the heap
+        let cell = self.stack.heapify(index);   // move value to the heap
+        if self.gc.track(&cell) { self.gc.collect(); }
         self.done()
     }
 
@@ -169,11 +505,40 @@ impl VM {
         self.done()
     }
 
+    /// Reserves space on the stack for a number of locals,
+    /// which are uninitialized until they're first assigned to.
+    #[inline]
+    pub fn reserve(&mut self) -> Result<(), Trace> {
+        let count = self.next_number();
+        for _ in 0..count { self.stack.push_data(Data::NotInit); }
+        self.done()
+    }
+
+    /// Raises an error if a variable is used before it's been assigned to.
+    fn initialized(&mut self, data: &Data) -> Result<(), Trace> {
+        let uninit = match data {
+            Data::NotInit   => true,
+            Data::Heaped(h) => *h.borrow() == Data::NotInit,
+            _               => false,
+        };
+
+        if uninit {
+            return Err(Trace::error(
+                "Reference",
+                "Variable referenced before assignment; it has not been initialized",
+                vec![self.closure.lambda.index_span(self.ip)],
+            ));
+        }
+
+        Ok(())
+    }
+
     /// Push a copy of a variable's value onto the stack.
     #[inline]
     pub fn load(&mut self) -> Result<(), Trace> {
         let index = self.next_number();
         let data = self.stack.local_data(index);
+        self.initialized(&data)?;
         self.stack.push_data(data);
         self.done()
     }
@@ -184,7 +549,9 @@ impl VM {
         let index = self.next_number();
         // NOTE: should heaped data should only be present for variables?
         // self.closure.captures[index].borrow().to_owned()
-        self.stack.push_data(self.closure.captures[index].borrow().to_owned());
+        let data = self.closure.captures[index].borrow().to_owned();
+        self.initialized(&data)?;
+        self.stack.push_data(data);
         self.done()
     }
 
@@ -208,7 +575,13 @@ impl VM {
     #[inline]
     pub fn print(&mut self) -> Result<(), Trace> {
         let data = self.stack.pop_data();
-        println!("{}", data);
+        if let Err(error) = writeln!(self.output.0, "{}", data) {
+            return Err(Trace::error(
+                "Output",
+                &format!("Could not print the data '{}': {}", data, error),
+                vec![self.closure.lambda.index_span(self.ip)],
+            ));
+        }
         self.stack.push_data(data);
         self.done()
     }
@@ -232,11 +605,7 @@ impl VM {
 
         let d = match self.stack.pop_data() {
             Data::Label(n, d) if *n == kind => d,
-            other => return Err(Trace::error(
-                "Pattern Matching",
-                &format!("The data '{}' does not match the Label '{}'", other, kind),
-                vec![self.closure.lambda.index_span(self.ip)],
-            )),
+            other => return self.mismatch(&format!("The data '{}' does not match the Label '{}'", other, kind)),
         };
 
         self.stack.push_data(*d);
@@ -248,42 +617,415 @@ impl VM {
         let data = self.stack.pop_data();
 
         if data != expected {
-            return Err(Trace::error(
+            return self.mismatch(&format!("The data '{}' does not match the expected data '{}'", data, expected));
+        }
+
+        self.done()
+    }
+
+    /// Collects a number of items on the top of the stack into a tuple.
+    /// The topmost item on the stack is the last item in the tuple.
+    pub fn tuple(&mut self) -> Result<(), Trace> {
+        let length = self.next_number();
+        let mut items = Vec::with_capacity(length);
+        for _ in 0..length { items.push(self.stack.pop_data()); }
+        items.reverse();
+
+        self.stack.push_data(Data::Tuple(items));
+        self.done()
+    }
+
+    /// Destructures a tuple of an expected length,
+    /// pushing its items onto the stack in reverse order,
+    /// so that the first item is on top.
+    fn un_tuple(&mut self) -> Result<(), Trace> {
+        let length = self.next_number();
+
+        let items = match self.stack.pop_data() {
+            Data::Tuple(t) if t.len() == length => t,
+            other => return self.mismatch(&format!("The data '{}' is not a tuple of length {}", other, length)),
+        };
+
+        for item in items.into_iter().rev() {
+            self.stack.push_data(item);
+        }
+        self.done()
+    }
+
+    /// Collects a number of items on the top of the stack into a list.
+    /// The topmost item on the stack is the last item in the list.
+    pub fn list(&mut self) -> Result<(), Trace> {
+        let length = self.next_number();
+        let mut list = List::empty();
+        for _ in 0..length { list = list.prepend(self.stack.pop_data()); }
+
+        self.stack.push_data(Data::List(list));
+        self.done()
+    }
+
+    /// Prepends the item below the top of the stack
+    /// to the list on the top of the stack.
+    pub fn cons(&mut self) -> Result<(), Trace> {
+        let tail = match self.stack.pop_data() {
+            Data::List(l) => l,
+            other => return Err(Trace::error(
+                "Type",
+                &format!("The data '{}' is not a list, so nothing can be put in front of it", other),
+                vec![self.closure.lambda.index_span(self.ip)],
+            )),
+        };
+        let head = self.stack.pop_data();
+
+        self.stack.push_data(Data::List(tail.prepend(head)));
+        self.done()
+    }
+
+    /// Destructures a list of an expected length,
+    /// pushing its items onto the stack in reverse order,
+    /// so that the first item is on top.
+    fn un_list(&mut self) -> Result<(), Trace> {
+        let length = self.next_number();
+
+        let items = match self.stack.pop_data() {
+            Data::List(l) if l.len() == length => l.iter().cloned().collect::<Vec<_>>(),
+            other => return self.mismatch(&format!("The data '{}' is not a list of length {}", other, length)),
+        };
+
+        for item in items.into_iter().rev() {
+            self.stack.push_data(item);
+        }
+        self.done()
+    }
+
+    /// Destructures a non-empty list,
+    /// pushing its tail and then its head onto the stack.
+    fn un_cons(&mut self) -> Result<(), Trace> {
+        let (head, tail) = match self.stack.pop_data() {
+            Data::List(l) => match l.uncons() {
+                Some(split) => split,
+                None => return self.mismatch("The list is empty, so it has no head or tail"),
+            },
+            other => return self.mismatch(&format!("The data '{}' is not a list", other)),
+        };
+
+        self.stack.push_data(Data::List(tail));
+        self.stack.push_data(head);
+        self.done()
+    }
+
+    /// Compares two values using a comparison opcode.
+    fn compare<T: PartialOrd>(opcode: Opcode, left: T, right: T) -> bool {
+        match opcode {
+            Opcode::Less         => left <  right,
+            Opcode::LessEqual    => left <= right,
+            Opcode::Greater      => left >  right,
+            Opcode::GreaterEqual => left >= right,
+            _ => unreachable!("Expected a comparison"),
+        }
+    }
+
+    /// Applies a binary operator to the two values on top of the stack,
+    /// where the right operand is on top.
+    /// Raises a type error if the operator can't be applied to the operands.
+    fn binop(&mut self, opcode: Opcode) -> Result<(), Trace> {
+        let right = self.stack.pop_data();
+        let left  = self.stack.pop_data();
+
+        let result = match (opcode, left, right) {
+            (Opcode::Add, Data::Real(l),   Data::Real(r))   => Data::Real(l + r),
+            (Opcode::Add, Data::String(l), Data::String(r)) => {
+                self.allocate(l.len() + r.len())?;
+                Data::String(l + &r)
+            },
+            (Opcode::Add, Data::List(l),   Data::List(r))   => {
+                // the right list is shared, the left is copied
+                self.allocate(l.len() * List::NODE_SIZE)?;
+                Data::List(l.iter().chain(r.iter()).cloned().collect())
+            },
+            (Opcode::Sub, Data::Real(l), Data::Real(r)) => Data::Real(l - r),
+            (Opcode::Mul, Data::Real(l), Data::Real(r)) => Data::Real(l * r),
+            (Opcode::Div, Data::Real(l), Data::Real(r)) => Data::Real(l / r),
+            (Opcode::Rem, Data::Real(l), Data::Real(r)) => Data::Real(l % r),
+
+            // any two values can be compared for equality
+            (Opcode::Equal,    l, r) => Data::Boolean(l == r),
+            (Opcode::NotEqual, l, r) => Data::Boolean(l != r),
+
+            (op @ (Opcode::Less | Opcode::LessEqual | Opcode::Greater | Opcode::GreaterEqual), l, r) => {
+                match (l, r) {
+                    (Data::Real(l),   Data::Real(r))   => Data::Boolean(VM::compare(op, l, r)),
+                    (Data::String(l), Data::String(r)) => Data::Boolean(VM::compare(op, l, r)),
+                    (l, r) => return Err(self.type_error(op, l, r)),
+                }
+            },
+
+            (Opcode::And, Data::Boolean(l), Data::Boolean(r)) => Data::Boolean(l && r),
+            (Opcode::Or,  Data::Boolean(l), Data::Boolean(r)) => Data::Boolean(l || r),
+
+            (op, l, r) => return Err(self.type_error(op, l, r)),
+        };
+
+        self.stack.push_data(result);
+        self.done()
+    }
+
+    /// Builds the error raised when a binary operator is applied to the wrong types.
+    fn type_error(&mut self, opcode: Opcode, left: Data, right: Data) -> Trace {
+        let operator = BinOp::from_opcode(opcode).expect("Expected a binary operator");
+
+        Trace::error(
+            "Type",
+            &format!("The operator '{}' can not be applied to '{}' and '{}'", operator, left, right),
+            vec![self.closure.lambda.index_span(self.ip)],
+        )
+    }
+
+    /// Negates the boolean on top of the stack.
+    fn not(&mut self) -> Result<(), Trace> {
+        let negated = match self.stack.pop_data() {
+            Data::Boolean(b) => Data::Boolean(!b),
+            other => return Err(Trace::error(
+                "Type",
+                &format!("The data '{}' is not a boolean, so it can not be negated", other),
+                vec![self.closure.lambda.index_span(self.ip)],
+            )),
+        };
+
+        self.stack.push_data(negated);
+        self.done()
+    }
+
+    /// Negates the number on top of the stack.
+    fn neg(&mut self) -> Result<(), Trace> {
+        let negated = match self.stack.pop_data() {
+            Data::Real(n) => Data::Real(-n),
+            other => return Err(Trace::error(
+                "Type",
+                &format!("The data '{}' is not a number, so it can not be negated", other),
+                vec![self.closure.lambda.index_span(self.ip)],
+            )),
+        };
+
+        self.stack.push_data(negated);
+        self.done()
+    }
+
+    /// Jumps forward a number of bytes,
+    /// counting from the end of the jump instruction.
+    pub fn jump(&mut self) -> Result<(), Trace> {
+        let offset = self.next_number();
+        self.next();
+        self.ip += offset;
+        Ok(())
+    }
+
+    /// Jumps backward a number of bytes,
+    /// counting from the end of the jump instruction.
+    pub fn jump_back(&mut self) -> Result<(), Trace> {
+        let offset = self.next_number();
+        self.next();
+        self.ip -= offset;
+        Ok(())
+    }
+
+    /// Pops a condition off the stack, jumping forward if it's false.
+    /// Raises a type error if the condition isn't a boolean.
+    pub fn jump_false(&mut self) -> Result<(), Trace> {
+        let offset = self.next_number();
+
+        match self.stack.pop_data() {
+            Data::Boolean(true)  => (),
+            Data::Boolean(false) => self.ip += offset,
+            other => return Err(Trace::error(
+                "Type",
+                &format!("The condition '{}' is not a boolean", other),
+                vec![self.closure.lambda.index_span(self.ip)],
+            )),
+        }
+
+        self.done()
+    }
+
+    /// Installs a handler for the arm of a match being tried.
+    pub fn handle(&mut self) -> Result<(), Trace> {
+        let offset = self.next_number();
+        self.handlers.push(Handler {
+            height: self.stack.stack.len(),
+            ip:     self.ip + 1 + offset,
+        });
+        self.done()
+    }
+
+    /// Removes the handler for the arm of a match once its pattern has matched.
+    pub fn unhandle(&mut self) -> Result<(), Trace> {
+        self.handlers.pop().expect("Expected a handler to remove");
+        self.done()
+    }
+
+    /// Called when some data doesn't match a pattern.
+    /// If an arm of a match is being tried,
+    /// the stack is restored and the next arm is tried;
+    /// otherwise a pattern matching error is raised.
+    fn mismatch(&mut self, message: &str) -> Result<(), Trace> {
+        match self.handlers.pop() {
+            Some(Handler { height, ip }) => {
+                self.stack.truncate(height);
+                self.ip = ip;
+                Ok(())
+            },
+            None => Err(Trace::error(
                 "Pattern Matching",
-                &format!("The data '{}' does not match the expected data '{}'", data, expected),
+                message,
                 vec![self.closure.lambda.index_span(self.ip)],
-            ));
+            )),
+        }
+    }
+
+    /// Raises an error because no arm of a match matched the value on top of the stack.
+    pub fn no_match(&mut self) -> Result<(), Trace> {
+        let value = self.stack.pop_data();
+        Err(Trace::error(
+            "Match",
+            &format!("No arm of the match matched the data '{}'", value),
+            vec![self.closure.lambda.index_span(self.ip)],
+        ))
+    }
+
+    /// Pops a tuple of field names off the stack,
+    /// as used by `Record` and `UnRecord`.
+    fn field_names(&mut self) -> Vec<String> {
+        match self.stack.pop_data() {
+            Data::Tuple(names) => names.into_iter()
+                .map(|n| match n {
+                    Data::String(s) => s,
+                    _ => unreachable!("Expected a field name"),
+                })
+                .collect(),
+            _ => unreachable!("Expected a tuple of field names"),
         }
+    }
+
+    /// Collects a number of values on the top of the stack into a record.
+    /// The names of the record's fields are on the top of the stack,
+    /// followed by each field's value.
+    pub fn record(&mut self) -> Result<(), Trace> {
+        let names = self.field_names();
+        let mut fields = Vec::with_capacity(names.len());
+        for name in names.into_iter().rev() {
+            fields.push((name, self.stack.pop_data()));
+        }
+        fields.sort_by(|a, b| a.0.cmp(&b.0));
+
+        self.stack.push_data(Data::Record(fields));
+        self.done()
+    }
+
+    /// Destructures a record, pushing the values of the expected fields
+    /// onto the stack in reverse order, so that the first field is on top.
+    /// Fields that aren't expected are ignored.
+    fn un_record(&mut self) -> Result<(), Trace> {
+        let names = self.field_names();
+
+        let mut fields = match self.stack.pop_data() {
+            Data::Record(r) => r,
+            other => return self.mismatch(&format!("The data '{}' is not a record", other)),
+        };
+
+        let mut values = Vec::with_capacity(names.len());
+        for name in names.iter() {
+            match fields.iter().position(|(f, _)| f == name) {
+                Some(i) => values.push(fields.swap_remove(i).1),
+                None => return self.mismatch(&format!("The record is missing the field '{}'", name)),
+            }
+        }
+
+        for value in values.into_iter().rev() {
+            self.stack.push_data(value);
+        }
+        self.done()
+    }
+
+    /// Looks up a field in the record on the top of the stack,
+    /// replacing the record with the field's value.
+    pub fn index(&mut self) -> Result<(), Trace> {
+        let name = match self.stack.pop_data() {
+            Data::String(s) => s,
+            _ => unreachable!("Expected a field name"),
+        };
+
+        let fields = match self.stack.pop_data() {
+            Data::Record(r) => r,
+            other => return Err(Trace::error(
+                "Type",
+                &format!("The data '{}' is not a record, so it has no field '{}'", other, name),
+                vec![self.closure.lambda.index_span(self.ip)],
+            )),
+        };
+
+        let value = match fields.into_iter().find(|(f, _)| f == &name) {
+            Some((_, v)) => v,
+            None => return Err(Trace::error(
+                "Field",
+                &format!("The record does not have the field '{}'", name),
+                vec![self.closure.lambda.index_span(self.ip)],
+            )),
+        };
 
+        self.stack.push_data(value);
         self.done()
     }
 
     /// Call a function on the top of the stack, passing the next value as an argument.
+    /// Calling a fiber resumes it; see `resume`.
+    /// The state of the calling function is saved,
+    /// and restored once the called function returns.
     pub fn call(&mut self) -> Result<(), Trace> {
         let fun = match self.stack.pop_data() {
             Data::Closure(c) => *c,
+            Data::Fiber(f)   => return self.resume(f),
+            Data::Host(h)    => return self.host(h),
             o => return Err(Trace::error(
                 "Call",
                 &format!("The data '{}' is not a function and can not be called", o),
                 vec![self.closure.lambda.index_span(self.ip)],
             )),
         };
+        self.check_depth(self.depth())?;
         let arg = self.stack.pop_data();
 
+        self.callers.push(Caller {
+            closure:  mem::replace(&mut self.closure, fun),
+            ip:       mem::replace(&mut self.ip, 0),
+            handlers: mem::take(&mut self.handlers),
+        });
+
         self.stack.push_frame();
         self.stack.push_data(arg);
-        // println!("entering...");
-        // TODO: keep the passerine call stack separated from the rust call stack.
-        match self.run(fun) {
-            Ok(()) => (),
-            Err(mut trace) => {
-                trace.add_context(self.closure.lambda.index_span(self.ip));
-                return Err(trace);
+        Ok(())
+    }
+
+    /// Calls a function in tail position,
+    /// replacing the frame of the current function rather than adding a new one.
+    /// The called function returns directly to the caller of the current one.
+    /// Fibers and host functions are called like normal.
+    pub fn tail_call(&mut self) -> Result<(), Trace> {
+        let fun = match self.stack.pop_data() {
+            Data::Closure(c) => *c,
+            other => {
+                self.stack.push_data(other);
+                return self.call();
             },
         };
-        // println!("exiting...");
+        let arg = self.stack.pop_data();
 
-        self.done()
+        self.stack.drop_frame();
+        self.stack.push_frame();
+        self.stack.push_data(arg);
+
+        self.closure  = fun;
+        self.ip       = 0;
+        self.handlers = vec![];
+        Ok(())
     }
 
     /// Return a value from a function.
@@ -300,39 +1042,178 @@ impl VM {
         for _ in 0..locals { self.del()?; }
 
         self.stack.pop_frame();    // remove the frame
-        self.stack.push_data(val); // push the return value
-        self.terminate()
-    }
-
-    pub fn closure(&mut self) -> Result<(), Trace> {
-        let index = self.next_number();
-
-        let lambda = match self.closure.lambda.constants[index].clone() {
-            Data::Lambda(lambda) => *lambda,
-            _ => unreachable!("Expected a lambda to be wrapped with a closure"),
-        };
 
-        let mut closure = Closure::wrap(lambda);
+        // return to the function that called this one
+        if let Some(caller) = self.callers.pop() {
+            self.closure  = caller.closure;
+            self.ip       = caller.ip;
+            self.handlers = caller.handlers;
+            self.stack.push_data(val); // push the return value
+            return self.done();
+        }
 
-        for captured in closure.lambda.captures.iter() /* .rev */ {
-            let reference = match captured {
-                Captured::Local(index) => {
-                    match self.stack.local_data(*index) {
-                        Data::Heaped(h) => h,
-                        _ => unreachable!("Expected data to be on the heap"),
-                    }
+        // a fiber that returns is done
+        if let Some(resumer) = self.resumers.pop() {
+            mem::drop(self.swap(resumer.fiber));
+            match resumer.resumed {
+                Some(fiber) => {
+                    *fiber.borrow_mut::<Fiber>() = Fiber::empty(Status::Done);
+                    self.stack.push_data(val);
                 },
-                Captured::Nonlocal(upvalue) => self.closure.captures[*upvalue].clone(),
-            };
-            closure.captures.push(reference)
+                None => self.stack.push_data(VM::result("Ok", val)),
+            }
+            return self.done();
         }
 
-        self.stack.push_data(Data::Closure(Box::new(closure)));
-        self.done()
+        self.stack.push_data(val);
+        self.terminate()
     }
-}
 
-#[cfg(test)]
+    /// Wraps the closure on top of the stack in a new fiber.
+    pub fn fiber(&mut self) -> Result<(), Trace> {
+        let closure = match self.stack.pop_data() {
+            Data::Closure(c) => *c,
+            _ => unreachable!("Expected a closure to be wrapped with a fiber"),
+        };
+
+        self.stack.push_data(Data::Fiber(FiberRef::new(Fiber::new(closure))));
+        self.done()
+    }
+
+    /// Resumes a suspended fiber, passing it the value on top of the stack.
+    /// The first time a fiber is resumed, the value is passed to its closure;
+    /// afterwards, the value is what the fiber's last `yield` evaluates to.
+    /// The fiber runs until it yields or returns,
+    /// at which point the value is passed back to the resuming fiber.
+    pub fn resume(&mut self, fiber: FiberRef) -> Result<(), Trace> {
+        // a fiber that yielded keeps the calls it was nested in
+        let depth = self.depth() + 1;
+        self.check_depth(depth + fiber.borrow::<Fiber>().callers.len())?;
+        let arg = self.stack.pop_data();
+
+        let status = fiber.borrow::<Fiber>().status;
+        let message = match status {
+            Status::Suspended => None,
+            Status::Running   => Some("The fiber is already running, so it can not be resumed"),
+            Status::Done      => Some("The fiber is done, so it can not be resumed"),
+        };
+
+        if let Some(message) = message {
+            return Err(Trace::error(
+                "Fiber",
+                message,
+                vec![self.closure.lambda.index_span(self.ip)],
+            ));
+        }
+
+        let state   = mem::replace(&mut *fiber.borrow_mut::<Fiber>(), Fiber::empty(Status::Running));
+        let resumer = self.swap(state);
+        self.resumers.push(Resumer { fiber: resumer, resumed: Some(fiber), depth });
+        self.stack.push_data(arg);
+        Ok(())
+    }
+
+    /// Calls a host function with the value on top of the stack.
+    /// Errors returned by the host function are raised at the call site.
+    pub fn host(&mut self, function: FFIFunction) -> Result<(), Trace> {
+        let arg = self.stack.pop_data();
+
+        match function.call(arg) {
+            Ok(data) => self.stack.push_data(data),
+            Err(message) => return Err(Trace::error(
+                "Host",
+                &message,
+                vec![self.closure.lambda.index_span(self.ip)],
+            )),
+        }
+
+        self.done()
+    }
+
+    /// Crashes the running fiber, raising the value on top of the stack.
+    pub fn raise(&mut self) -> Result<(), Trace> {
+        let data = self.stack.pop_data();
+        Err(Trace::raise(data, vec![self.closure.lambda.index_span(self.ip)]))
+    }
+
+    /// Runs the closure on top of the stack in a new fiber.
+    /// If the closure returns a value, the `try` evaluates to `Result.Ok value`;
+    /// if the fiber crashes, the `try` evaluates to `Result.Error error`.
+    pub fn try_(&mut self) -> Result<(), Trace> {
+        let closure = match self.stack.pop_data() {
+            Data::Closure(c) => *c,
+            _ => unreachable!("Expected a closure to be tried"),
+        };
+        let depth = self.depth() + 1;
+        self.check_depth(depth)?;
+
+        let resumer = self.swap(Fiber::new(closure));
+        self.resumers.push(Resumer { fiber: resumer, resumed: None, depth });
+        self.stack.push_data(Data::Unit);
+        Ok(())
+    }
+
+    /// Wraps some data in a `Result` label, i.e. `Result.Ok data`.
+    fn result(variant: &str, data: Data) -> Data {
+        Data::Label(Box::new(format!("Result.{}", variant)), Box::new(data))
+    }
+
+    /// Suspends the running fiber,
+    /// passing the value on top of the stack back to the fiber that resumed it.
+    pub fn yield_val(&mut self) -> Result<(), Trace> {
+        let message = match self.resumers.last() {
+            None => Some("Can not yield outside of a fiber"),
+            Some(Resumer { resumed: None, .. }) => Some("Can not yield out of a try"),
+            Some(_) => None,
+        };
+
+        if let Some(message) = message {
+            return Err(Trace::error(
+                "Fiber",
+                message,
+                vec![self.closure.lambda.index_span(self.ip)],
+            ));
+        }
+
+        let resumer = self.resumers.pop().unwrap();
+        let fiber   = resumer.resumed.unwrap();
+        let val = self.stack.pop_data();
+        self.next(); // continue after the yield when resumed
+        *fiber.borrow_mut::<Fiber>() = self.swap(resumer.fiber);
+        self.stack.push_data(val);
+        self.done()
+    }
+
+    pub fn closure(&mut self) -> Result<(), Trace> {
+        let index = self.next_number();
+
+        let lambda = match self.closure.lambda.constants[index].clone() {
+            Data::Lambda(lambda) => *lambda,
+            _ => unreachable!("Expected a lambda to be wrapped with a closure"),
+        };
+        self.allocate(lambda.code.len())?;
+
+        let mut closure = Closure::wrap(lambda);
+
+        for captured in closure.lambda.captures.iter() /* .rev */ {
+            let reference = match captured {
+                Captured::Local(index) => {
+                    match self.stack.local_data(*index) {
+                        Data::Heaped(h) => h,
+                        _ => unreachable!("Expected data to be on the heap"),
+                    }
+                },
+                Captured::Nonlocal(upvalue) => self.closure.captures[*upvalue].clone(),
+            };
+            closure.captures.push(reference)
+        }
+
+        self.stack.push_data(Data::Closure(Box::new(closure)));
+        self.done()
+    }
+}
+
+#[cfg(test)]
 mod test {
     use super::*;
     use crate::compiler::{
@@ -341,7 +1222,36 @@ mod test {
         lex::lex,
         gen::gen,
     };
-    use crate::common::source::Source;
+    use crate::common::{
+        source::Source,
+        number::split_number,
+    };
+    use std::{
+        rc::Rc,
+        cell::RefCell,
+    };
+
+    fn compile(source: &str) -> Closure {
+        let lambda = lex(Source::source(source))
+            .and_then(parse)
+            .and_then(desugar)
+            .and_then(gen)
+            .unwrap();
+        Closure::wrap(lambda)
+    }
+
+    /// Runs a program that is expected to fail, returning the error.
+    fn run_err(source: &str) -> Trace {
+        VM::init().run(compile(source)).unwrap_err()
+    }
+
+    /// Runs a program that is expected to fail with an error of a kind,
+    /// whose message contains some text.
+    fn assert_err(source: &str, kind: &str, message: &str) {
+        let trace = run_err(source);
+        assert_eq!(trace.kind(), kind, "{}", source);
+        assert!(format!("{}", trace).contains(message), "{}", trace);
+    }
 
     fn inspect(source: &str) -> VM {
         let lambda = lex(Source::source(source))
@@ -381,6 +1291,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn fun_scope() {
         // y = (x -> { y = x; y ) 7.0; y
         let mut vm = inspect("one = 1.0\npi = 3.14\ne = 2.72\n\nx = w -> pi\nx 37.6");
@@ -408,6 +1319,757 @@ mod test {
         ");
     }
 
+    #[test]
+    fn tuple_swap() {
+        let mut vm = inspect("a = 1.0; b = 2.0; (a, b) = (b, a); (a, b)");
+        let swapped = vm.stack.pop_data();
+        assert_eq!(swapped, Data::Tuple(vec![Data::Real(2.0), Data::Real(1.0)]));
+    }
+
+    #[test]
+    fn tuple_nested() {
+        let mut vm = inspect("
+            first = (x, _y) -> x
+            ((a, b), c) = ((true, ()), \"hi\")
+            first (b, c)
+        ");
+        let b = vm.stack.pop_data();
+        assert_eq!(b, Data::Unit);
+    }
+
+    #[test]
+    fn tuple_arity() {
+        assert_err("(a, b) = (1.0, 2.0, 3.0)", "Pattern Matching", "is not a tuple of length 2");
+    }
+
+    #[test]
+    fn record_index() {
+        let mut vm = inspect("
+            age = 16.0
+            isaac = { name: \"Isaac\", age }
+            isaac.age
+        ");
+        assert_eq!(vm.stack.pop_data(), Data::Real(16.0));
+    }
+
+    #[test]
+    fn record_pattern() {
+        let mut vm = inspect("
+            isaac = Person {
+                name: \"Isaac Clayton\",
+                age: 16.0,
+                skill: Wizard \"High enough\",
+            }
+            Person { name: full_name, skill } = isaac
+            (full_name, skill)
+        ");
+        assert_eq!(
+            vm.stack.pop_data(),
+            Data::Tuple(vec![
+                Data::String("Isaac Clayton".to_string()),
+                Data::Label(
+                    Box::new("Wizard".to_string()),
+                    Box::new(Data::String("High enough".to_string())),
+                ),
+            ]),
+        );
+    }
+
+    #[test]
+    fn record_missing_field() {
+        assert_err("r = { a: 1.0 }; r.b", "Field", "does not have the field 'b'");
+        assert_err("{ b } = { a: 1.0, c: 2.0 }", "Pattern Matching", "is missing the field 'b'");
+    }
+
+    #[test]
+    fn list_head_tail() {
+        let mut vm = inspect("
+            numbers = [1.0, 2.0, 3.0]
+            [first & rest] = numbers
+            [second, third] = rest
+            [first, second, third]
+        ");
+
+        let list = vm.stack.pop_data();
+        let expected = vec![Data::Real(1.0), Data::Real(2.0), Data::Real(3.0)]
+            .into_iter().collect::<List>();
+        assert_eq!(list, Data::List(expected));
+    }
+
+    #[test]
+    fn list_cons() {
+        let mut vm = inspect("
+            rest = [2.0, 3.0]
+            [0.0, 1.0 & rest]
+        ");
+
+        let list = vm.stack.pop_data();
+        let expected = (0..4).map(|n| Data::Real(n as f64)).collect::<List>();
+        assert_eq!(list, Data::List(expected));
+    }
+
+    #[test]
+    fn list_mismatch() {
+        assert_err("[h & t] = []", "Pattern Matching", "The list is empty");
+        assert_err("[a, b] = [1.0]", "Pattern Matching", "is not a list of length 2");
+        assert_err("[0.0 & 1.0]", "Type", "is not a list, so nothing can be put in front of it");
+    }
+
+    #[test]
+    fn arithmetic() {
+        let mut vm = inspect("
+            linear = m b x -> b + m * x
+            linear 2.0 3.0 5.0 - 10.0 / 4.0 % 2.0
+        ");
+        assert_eq!(vm.stack.pop_data(), Data::Real(13.0 - 0.5));
+    }
+
+    #[test]
+    fn comparison() {
+        let mut vm = inspect("
+            a = 1.0 + 1.0 == 2.0 and \"a\" < \"b\"
+            b = not 3.0 >= 4.0 or false
+            (a, b, [1.0] + [2.0] != [1.0, 2.0])
+        ");
+        assert_eq!(
+            vm.stack.pop_data(),
+            Data::Tuple(vec![Data::Boolean(true), Data::Boolean(true), Data::Boolean(false)]),
+        );
+    }
+
+    #[test]
+    fn short_circuit() {
+        // the right operand would raise an error if it were run
+        let mut vm = inspect("
+            a = false and error \"and\"
+            b = true or error \"or\"
+            (a, b, true and false, false or true)
+        ");
+        assert_eq!(
+            vm.stack.pop_data(),
+            Data::Tuple(vec![
+                Data::Boolean(false),
+                Data::Boolean(true),
+                Data::Boolean(false),
+                Data::Boolean(true),
+            ]),
+        );
+    }
+
+    #[test]
+    fn negation() {
+        let mut vm = inspect("x = 2.0; -x * 3.0 - -1.0");
+        assert_eq!(vm.stack.pop_data(), Data::Real(-5.0));
+    }
+
+    #[test]
+    fn operator_type_error() {
+        let cases = [
+            ("1.0 + true", "The operator '+' can not be applied to '1' and 'true'"),
+            ("\"a\" < 1.0", "The operator '<' can not be applied to 'a' and '1'"),
+            ("not ()", "is not a boolean, so it can not be negated"),
+            ("1.0 and true", "The condition '1' is not a boolean"),
+            ("true and 1.0", "The condition '1' is not a boolean"),
+            ("-true", "is not a number, so it can not be negated"),
+        ];
+
+        for (source, message) in cases.iter() {
+            assert_err(source, "Type", message);
+        }
+    }
+
+    #[test]
+    fn if_else() {
+        let mut vm = inspect("
+            sign = n -> if n < 0.0 {
+                0.0 - 1.0
+            } else if n == 0.0 {
+                0.0
+            } else {
+                1.0
+            }
+
+            (sign (0.0 - 3.0), sign 0.0, sign 7.0, if false { 1.0 })
+        ");
+
+        assert_eq!(
+            vm.stack.pop_data(),
+            Data::Tuple(vec![
+                Data::Real(-1.0),
+                Data::Real(0.0),
+                Data::Real(1.0),
+                Data::Unit,
+            ]),
+        );
+    }
+
+    #[test]
+    fn if_block_condition() {
+        // a block can be a condition, and assignments in branches are hoisted
+        let mut vm = inspect("
+            if { x = 2.0; x > 1.0 } { y = x } else { y = 0.0 }
+            y
+        ");
+        assert_eq!(vm.stack.pop_data(), Data::Real(2.0));
+    }
+
+    #[test]
+    fn if_not_boolean() {
+        run_err("if 1.0 { 2.0 }");
+    }
+
+    #[test]
+    fn jump_back() {
+        // counts down from three using a backward jump
+        let mut lambda = Lambda::empty();
+        let three = lambda.index_data(Data::Real(3.0));
+        let one   = lambda.index_data(Data::Real(1.0));
+        let zero  = lambda.index_data(Data::Real(0.0));
+
+        lambda.emit(Opcode::Con);
+        lambda.emit_bytes(&mut split_number(three));
+        let start = lambda.code.len();
+        lambda.emit(Opcode::Con);
+        lambda.emit_bytes(&mut split_number(one));
+        lambda.emit(Opcode::Sub);
+        lambda.emit(Opcode::Copy);
+        lambda.emit(Opcode::Con);
+        lambda.emit_bytes(&mut split_number(zero));
+        lambda.emit(Opcode::Equal);
+        let exit = lambda.emit_jump(Opcode::JumpFalse);
+        let end  = lambda.emit_jump(Opcode::Jump);
+        lambda.patch_jump(exit);
+        lambda.emit_jump_back(start);
+        lambda.patch_jump(end);
+
+        let mut vm = VM::init();
+        vm.run(Closure::wrap(lambda)).unwrap();
+        assert_eq!(vm.stack.pop_data(), Data::Real(0.0));
+    }
+
+    #[test]
+    fn match_arms() {
+        let mut vm = inspect("
+            describe = x -> match x {
+                0.0 -> \"zero\"
+                (a, b) -> a + b
+                [] -> \"empty\"
+                [h & _t] -> h
+                Some { name } -> name
+                other -> other
+            }
+
+            (
+                describe 0.0,
+                describe (\"a\", \"b\"),
+                describe [],
+                describe [\"head\", \"tail\"],
+                describe (Some { name: \"record\" }),
+                describe \"other\",
+            )
+        ");
+
+        let strings = vec!["zero", "ab", "empty", "head", "record", "other"];
+        assert_eq!(
+            vm.stack.pop_data(),
+            Data::Tuple(strings.into_iter().map(|s| Data::String(s.to_string())).collect()),
+        );
+    }
+
+    #[test]
+    fn match_nested() {
+        // a failed arm restores the stack, even partway through destructuring
+        let mut vm = inspect("
+            x = ((1.0, 2.0), [3.0])
+            y = match x {
+                ((a, 5.0), [c]) -> 0.0
+                ((a, b), [c]) -> match c { 4.0 -> 0.0; n -> a + b + n }
+            }
+            y
+        ");
+        assert_eq!(vm.stack.pop_data(), Data::Real(6.0));
+    }
+
+    #[test]
+    fn match_scope() {
+        // arms bind their own variables, rather than assigning to enclosing ones
+        let mut vm = inspect("n = 5.0; m = match 3.0 { n -> n }; (m, n)");
+        assert_eq!(vm.stack.pop_data(), Data::Tuple(vec![Data::Real(3.0), Data::Real(5.0)]));
+
+        // a failed arm's partial bindings are rolled back
+        let mut vm = inspect("
+            a = 0.0
+            match (1.0, 2.0) {
+                (a, 3.0) -> a
+                (b, c) -> (a, b, c)
+            }
+        ");
+        assert_eq!(
+            vm.stack.pop_data(),
+            Data::Tuple(vec![Data::Real(0.0), Data::Real(1.0), Data::Real(2.0)]),
+        );
+
+        // so without an enclosing variable, it's undefined
+        let source = Source::source("match (1.0, 2.0) {\n(a, 3.0) -> a\n(b, c) -> (a, b, c)\n}");
+        assert!(lex(source).and_then(parse).and_then(desugar).and_then(gen).is_err());
+    }
+
+    #[test]
+    fn match_non_exhaustive() {
+        let trace = run_err("match (1.0, 2.0) { (a, b, c) -> a; [] -> 2.0 }");
+        assert!(format!("{}", trace).contains("matched the data '(1, 2)'"));
+    }
+
+    #[test]
+    fn discard() {
+        let mut vm = inspect("
+            (_, b, _) = (1.0, 2.0, 3.0)
+            [_ & rest] = [4.0, 5.0]
+            first = a _ -> a
+            (b, rest, first 6.0 7.0)
+        ");
+
+        assert_eq!(
+            vm.stack.pop_data(),
+            Data::Tuple(vec![
+                Data::Real(2.0),
+                Data::List(vec![Data::Real(5.0)].into_iter().collect()),
+                Data::Real(6.0),
+            ]),
+        );
+    }
+
+    #[test]
+    fn guard() {
+        let mut vm = inspect("
+            classify = n -> match n {
+                (a, b) | a == b -> \"pair\"
+                (a | a > 10.0, _) -> \"big\"
+                x | x == 0.0 -> \"zero\"
+                _ -> \"other\"
+            }
+
+            (
+                classify 0.0,
+                classify (2.0, 2.0),
+                classify (11.0, 2.0),
+                classify (1.0, 2.0),
+            )
+        ");
+
+        let strings = vec!["zero", "pair", "big", "other"];
+        assert_eq!(
+            vm.stack.pop_data(),
+            Data::Tuple(strings.into_iter().map(|s| Data::String(s.to_string())).collect()),
+        );
+    }
+
+    #[test]
+    fn guard_assign() {
+        // outside of a match, a false guard is an error
+        for (source, fails) in &[("x | x > 1.0 = 2.0", false), ("x | x > 1.0 = 0.0", true)] {
+            assert_eq!(VM::init().run(compile(source)).is_err(), *fails);
+        }
+    }
+
+    #[test]
+    fn recursive_capture() {
+        // functions can refer to themselves,
+        // because variables are declared before they are assigned to
+        inspect("
+            loop = x -> loop
+            loop (loop ())
+        ");
+    }
+
+    #[test]
+    fn fiber_generator() {
+        let mut vm = inspect("
+            counter = fiber {
+                loop = i -> { yield i; loop (i + 1.0) }
+                loop 0.0
+            }
+            a = counter ()
+            b = counter ()
+            c = counter ()
+            (a, b, c)
+        ");
+
+        assert_eq!(
+            vm.stack.pop_data(),
+            Data::Tuple(vec![Data::Real(0.0), Data::Real(1.0), Data::Real(2.0)]),
+        );
+    }
+
+    #[test]
+    fn fiber_passing_yield() {
+        let mut vm = inspect("
+            passing = fiber {
+                result = yield \"banana\"
+                (result, \"yes\")
+            }
+            first = passing ()
+            second = passing \"apple\"
+            (first, second)
+        ");
+
+        assert_eq!(
+            vm.stack.pop_data(),
+            Data::Tuple(vec![
+                Data::String("banana".to_string()),
+                Data::Tuple(vec![
+                    Data::String("apple".to_string()),
+                    Data::String("yes".to_string()),
+                ]),
+            ]),
+        );
+    }
+
+    #[test]
+    fn fiber_errors() {
+        let cases = [
+            ("once = fiber 1.0; once (); once ()", "is done"),
+            ("yield 1.0", "outside of a fiber"),
+            ("crash = fiber { 1.0 + true }; crash ()", "can not be applied"),
+        ];
+
+        for (source, message) in cases.iter() {
+            let trace = run_err(source);
+            assert!(format!("{}", trace).contains(message));
+        }
+    }
+
+    #[test]
+    fn try_ok() {
+        let mut vm = inspect("try (1.0 + 2.0)");
+
+        assert_eq!(
+            vm.stack.pop_data(),
+            Data::Label(Box::new("Result.Ok".to_string()), Box::new(Data::Real(3.0))),
+        );
+    }
+
+    #[test]
+    fn try_error() {
+        let mut vm = inspect("
+            doof = platypus -> {
+                if platypus == \"Perry\" {
+                    error Inator \"What!? Perry the platypus!?\"
+                } else {
+                    platypus
+                }
+            }
+
+            handle = result -> match result {
+                Result.Ok    ok          -> ok
+                Result.Error Inator text -> text
+                Result.Error other       -> \"crashed\"
+            }
+
+            (
+                handle (try (doof \"Phineas\")),
+                handle (try (doof \"Perry\")),
+                handle (try (1.0 + true)),
+            )
+        ");
+
+        assert_eq!(
+            vm.stack.pop_data(),
+            Data::Tuple(vec![
+                Data::String("Phineas".to_string()),
+                Data::String("What!? Perry the platypus!?".to_string()),
+                Data::String("crashed".to_string()),
+            ]),
+        );
+    }
+
+    #[test]
+    fn try_unwinds() {
+        // the error crashes through nested calls and fibers
+        let mut vm = inspect("
+            deep = n -> if n == 0.0 { error n } else { deep (n - 1.0) }
+            crash = fiber { x = 1.0; deep 3.0 }
+            (try (crash ()), try (yield ()), 2.0)
+        ");
+
+        assert_eq!(
+            vm.stack.pop_data(),
+            Data::Tuple(vec![
+                Data::Label(Box::new("Result.Error".to_string()), Box::new(Data::Real(0.0))),
+                Data::Label(
+                    Box::new("Result.Error".to_string()),
+                    Box::new(Data::String("Can not yield out of a try".to_string())),
+                ),
+                Data::Real(2.0),
+            ]),
+        );
+    }
+
+    #[test]
+    fn uncaught_error() {
+        let mut vm = VM::init();
+        let trace = vm.run(compile("f = x -> { y = x; error y }; f \"oops\"")).unwrap_err();
+        assert!(format!("{}", trace).contains("Runtime Uncaught Error: oops"));
+        // only the base frame remains
+        assert_eq!(vm.stack.stack.len(), 1);
+    }
+
+    /// Collects printed output so it can be inspected.
+    #[derive(Clone, Default)]
+    struct Captured(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    /// Fails to write anything.
+    struct Broken;
+
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("broken pipe"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    #[test]
+    fn print_output() {
+        let captured = Captured::default();
+        let mut vm = VM::init();
+        vm.set_output(captured.clone());
+
+        let closure = compile("print \"Hello\"; x = print (1.0, true); x");
+        assert_eq!(
+            vm.eval(closure),
+            Ok(Data::Tuple(vec![Data::Real(1.0), Data::Boolean(true)])),
+        );
+        assert_eq!(
+            String::from_utf8(captured.0.borrow().clone()).unwrap(),
+            "Hello\n(1, true)\n",
+        );
+    }
+
+    #[test]
+    fn print_broken_output() {
+        let mut vm = VM::init();
+        vm.set_output(Broken);
+
+        let trace = vm.run(compile("print 1.0")).unwrap_err();
+        assert!(format!("{}", trace).contains("Could not print the data '1': broken pipe"));
+    }
+
+    #[test]
+    fn deep_recursion() {
+        // calls don't recurse on the rust stack
+        let mut vm = inspect("
+            count = n -> if n == 0.0 { 0.0 } else { 1.0 + count (n - 1.0) }
+            count 50000.0
+        ");
+
+        assert_eq!(vm.stack.pop_data(), Data::Real(50000.0));
+    }
+
+    #[test]
+    fn recursion_limit() {
+        let cases = [
+            "forever = x -> 1.0 + forever x; forever ()",
+            "nest = x -> (fiber { nest x }) (); nest ()",
+        ];
+
+        for source in cases.iter() {
+            let mut vm = VM::init();
+            vm.set_recursion_limit(100);
+            let trace = vm.run(compile(source)).unwrap_err();
+            assert!(format!("{}", trace).contains("The recursion limit of 100 was exceeded"));
+        }
+
+        // calls are counted across fibers, not in each fiber separately
+        let mut vm = VM::init();
+        vm.set_recursion_limit(100);
+        let trace = vm.run(compile("
+            deep = d k -> if d == 0.0 { k () } else { r = deep (d - 1.0) k; r }
+            nest = n -> if n == 0.0 { 0.0 } else { deep 50.0 (() -> (fiber { nest (n - 1.0) }) ()) }
+            nest 50.0
+        ")).unwrap_err();
+        assert!(format!("{}", trace).contains("The recursion limit of 100 was exceeded"));
+
+        // the limit can be caught like any other error
+        let mut vm = VM::init();
+        vm.set_recursion_limit(100);
+        let result = vm.eval(compile("forever = x -> 1.0 + forever x; try (forever ())"));
+        assert!(matches!(result, Ok(Data::Label(n, _)) if *n == "Result.Error"));
+    }
+
+    #[test]
+    fn tail_calls() {
+        // with tail calls, loops don't grow the call stack
+        let source = "
+            countdown = n -> if n == 0.0 { \"done\" } else { countdown (n - 1.0) }
+            even = n -> match n { 0.0 -> true;  _ -> odd  (n - 1.0) }
+            odd  = n -> match n { 0.0 -> false; _ -> { m = n - 1.0; even m } }
+            (countdown 100000.0, even 10001.0)
+        ";
+
+        let mut vm = VM::init();
+        vm.set_recursion_limit(10);
+        assert_eq!(
+            vm.eval(compile(source)),
+            Ok(Data::Tuple(vec![Data::String("done".to_string()), Data::Boolean(false)])),
+        );
+    }
+
+    #[test]
+    fn tail_call_fallback() {
+        // fibers called in tail position are resumed like normal
+        let mut vm = inspect("
+            numbers = fiber { yield 1.0; 2.0 }
+            next = x -> numbers x
+            (next (), next ())
+        ");
+
+        assert_eq!(
+            vm.stack.pop_data(),
+            Data::Tuple(vec![Data::Real(1.0), Data::Real(2.0)]),
+        );
+    }
+
+    #[test]
+    fn fuel() {
+        let forever = "loop = x -> loop x; loop ()";
+        let mut vm = VM::init();
+        vm.set_fuel(Some(1000));
+
+        let trace = vm.run(compile(forever)).unwrap_err();
+        assert_eq!(trace.kind(), "Fuel");
+        assert!(trace.is_fatal());
+        assert_eq!(vm.fuel(), Some(0));
+        assert_eq!(vm.fuel_consumed(), 1000);
+
+        // fuel can't be caught by a try
+        vm.set_fuel(Some(1000));
+        let trace = vm.run(compile("x = try { loop = x -> loop x; loop () }; x")).unwrap_err();
+        assert_eq!(trace.kind(), "Fuel");
+
+        // once refueled, the vm can be used again
+        vm.set_fuel(Some(1000));
+        assert_eq!(vm.eval(compile("1.0 + 2.0")), Ok(Data::Real(3.0)));
+        assert!(vm.fuel().unwrap() < 1000);
+    }
+
+    #[test]
+    fn fuel_deterministic() {
+        let source = "fib = n -> if n < 2.0 { n } else { fib (n - 1.0) + fib (n - 2.0) }; fib 10.0";
+
+        let mut first = VM::init();
+        first.eval(compile(source)).unwrap();
+
+        let mut second = VM::init();
+        second.set_fuel(Some(first.fuel_consumed()));
+        assert_eq!(second.eval(compile(source)), Ok(Data::Real(55.0)));
+        assert_eq!(second.fuel(), Some(0));
+
+        let mut third = VM::init();
+        third.set_fuel(Some(first.fuel_consumed() - 1));
+        assert!(third.eval(compile(source)).is_err());
+    }
+
+    #[test]
+    fn memory_limit() {
+        let cases = [
+            "grow = s -> grow (s + s); grow \"ab\"",
+            "build = l -> build [1.0 & l]; build []",
+            "hold = fiber { big = \"a\" + \"b\"; loop = s -> { yield (); loop (s + s) }; loop big }
+             spin = () -> { hold (); spin () }
+             spin ()",
+        ];
+
+        for source in cases.iter() {
+            let mut vm = VM::init();
+            vm.set_memory_limit(Some(1_000_000));
+            let trace = vm.run(compile(source)).unwrap_err();
+            assert_eq!(trace.kind(), "Memory");
+            assert!(trace.is_fatal());
+            assert!(vm.memory() < 1_000_000);
+        }
+    }
+
+    #[test]
+    fn memory_within_limit() {
+        let mut vm = VM::init();
+        vm.set_memory_limit(Some(1_000_000));
+        let source = "fib = n -> if n < 2.0 { n } else { fib (n - 1.0) + fib (n - 2.0) }; fib 15.0";
+        assert_eq!(vm.eval(compile(source)), Ok(Data::Real(610.0)));
+
+        let before = vm.memory();
+        vm.stack.push_data(Data::String("a".repeat(10_000)));
+        assert!(vm.memory() >= before + 10_000);
+    }
+
+    #[test]
+    fn memory_across_runs() {
+        // data already on the stack counts towards the limit
+        let mut vm = VM::init();
+        vm.set_memory_limit(Some(1_000_000));
+        vm.stack.push_data(Data::String("a".repeat(900_000)));
+        vm.stack.push_frame();
+
+        let source = "grow = s n -> if n == 0.0 { s } else { grow (s + s) (n - 1.0) }; grow \"ab\" 17.0";
+        let trace = vm.run(compile(source)).unwrap_err();
+        assert_eq!(trace.kind(), "Memory");
+
+        let mut fresh = VM::init();
+        fresh.set_memory_limit(Some(1_000_000));
+        assert!(fresh.run(compile(source)).is_ok());
+    }
+
+    #[test]
+    fn interrupt() {
+        let mut vm = VM::init();
+        let handle = vm.interrupt_handle();
+
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            handle.interrupt();
+        });
+
+        let trace = vm.run(compile("forever = x -> forever x; forever ()")).unwrap_err();
+        interrupter.join().unwrap();
+        assert_eq!(trace.kind(), "Interrupted");
+        assert!(trace.is_fatal());
+        assert!(format!("{}", trace).contains("forever x"));
+
+        // the interrupt is cleared once the vm stops
+        assert!(!vm.interrupt_handle().is_interrupted());
+        assert_eq!(vm.eval(compile("1.0")), Ok(Data::Real(1.0)));
+
+        // an interrupt made before a run starts stops it straight away,
+        // so a timeout can't be missed
+        vm.interrupt_handle().interrupt();
+        assert_eq!(vm.run(compile("1.0")).unwrap_err().kind(), "Interrupted");
+        assert_eq!(vm.eval(compile("1.0")), Ok(Data::Real(1.0)));
+    }
+
+    #[test]
+    fn collect_garbage() {
+        let mut vm = VM::init();
+        let source = "f = () -> { loop = x -> loop x; 0.0 }; f (); f ()";
+        assert_eq!(vm.eval(compile(source)), Ok(Data::Real(0.0)));
+        assert!(vm.collect_garbage() >= 2);
+        assert_eq!(vm.collect_garbage(), 0);
+
+        // cycles are collected as the script runs
+        let source = "f = () -> { loop = x -> loop x; 0.0 }
+            repeat = n -> if n < 1.0 { () } else { f (); repeat (n - 1.0) }
+            repeat 10000.0";
+        assert_eq!(vm.eval(compile(source)), Ok(Data::Unit));
+        assert!(vm.gc.tracked() < 10000);
+    }
+
     // TODO: figure out how to make the following passerine code into a test
     // without entering into an infinite loop (which is the intended behaviour)
     // loop = ()